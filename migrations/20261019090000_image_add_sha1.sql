@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE image ADD COLUMN sha1 TEXT;
+CREATE INDEX image_sha1_idx ON image (sha1);
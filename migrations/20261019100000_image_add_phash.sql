@@ -0,0 +1,13 @@
+-- Add up migration script here
+ALTER TABLE image ADD COLUMN phash INTEGER;
+
+-- 将 64 位的感知哈希拆成 4 段并分别建立索引
+-- 根据抽屉原理，汉明距离不超过 3 的两个哈希至少有一段完全相同，因此可以先按段查出候选再精确计算距离
+ALTER TABLE image ADD COLUMN phash_0 INTEGER GENERATED ALWAYS AS ((phash >> 48) & 65535) VIRTUAL;
+ALTER TABLE image ADD COLUMN phash_1 INTEGER GENERATED ALWAYS AS ((phash >> 32) & 65535) VIRTUAL;
+ALTER TABLE image ADD COLUMN phash_2 INTEGER GENERATED ALWAYS AS ((phash >> 16) & 65535) VIRTUAL;
+ALTER TABLE image ADD COLUMN phash_3 INTEGER GENERATED ALWAYS AS (phash & 65535) VIRTUAL;
+CREATE INDEX image_phash_0_idx ON image (phash_0);
+CREATE INDEX image_phash_1_idx ON image (phash_1);
+CREATE INDEX image_phash_2_idx ON image (phash_2);
+CREATE INDEX image_phash_3_idx ON image (phash_3);
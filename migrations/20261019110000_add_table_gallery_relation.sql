@@ -0,0 +1,10 @@
+-- Add up migration script here
+CREATE TABLE gallery_relation (
+    gallery_id INTEGER NOT NULL,
+    related_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    overlap FLOAT NOT NULL,
+    created_at DATETIME NOT NULL,
+    PRIMARY KEY (gallery_id, related_id)
+);
+CREATE INDEX gallery_relation_related_id_idx ON gallery_relation (related_id);
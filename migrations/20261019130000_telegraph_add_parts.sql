@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- 分多篇发布的文章，按顺序记录每一篇的地址，url 列始终为第一篇
+ALTER TABLE telegraph ADD COLUMN parts TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- 记录文章的路径，以便之后原地编辑文章
+ALTER TABLE telegraph ADD COLUMN path TEXT NOT NULL DEFAULT '';
+
+UPDATE telegraph SET path = substr(url, length('https://telegra.ph/') + 1) WHERE url LIKE 'https://telegra.ph/%';
@@ -0,0 +1,16 @@
+-- Add up migration script here
+CREATE TABLE telegraph_account (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    access_token TEXT NOT NULL UNIQUE,
+    -- 连续发布失败的次数，发布成功后清零
+    failures INTEGER NOT NULL DEFAULT 0,
+    -- 失败次数过多后停用，停用的账号仍然可以用来编辑已有的文章
+    retired BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at DATETIME NOT NULL
+);
+
+-- 文章只能由创建它的账号编辑，因此需要记录每篇文章所属的账号
+CREATE TABLE telegraph_page (
+    path TEXT PRIMARY KEY NOT NULL,
+    account_id INTEGER NOT NULL
+);
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE message ADD COLUMN kind TEXT NOT NULL DEFAULT 'text';
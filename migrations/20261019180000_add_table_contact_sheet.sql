@@ -0,0 +1,6 @@
+-- Add up migration script here
+CREATE TABLE contact_sheet (
+    gallery_id INTEGER PRIMARY KEY NOT NULL,
+    url TEXT NOT NULL,
+    created_at DATETIME NOT NULL
+);
@@ -0,0 +1,6 @@
+-- Add up migration script here
+CREATE TABLE review (
+    gallery_id INTEGER PRIMARY KEY NOT NULL,
+    rule TEXT NOT NULL,
+    created_at DATETIME NOT NULL
+);
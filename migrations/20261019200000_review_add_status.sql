@@ -0,0 +1,5 @@
+-- Add up migration script here
+ALTER TABLE review ADD COLUMN status TEXT NOT NULL DEFAULT 'pending';
+ALTER TABLE review ADD COLUMN note TEXT;
+ALTER TABLE review ADD COLUMN message_id INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE review ADD COLUMN decided_at DATETIME;
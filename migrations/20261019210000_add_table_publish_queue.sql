@@ -0,0 +1,8 @@
+-- Add up migration script here
+CREATE TABLE publish_queue (
+    gallery_id INTEGER PRIMARY KEY NOT NULL,
+    position INTEGER NOT NULL,
+    created_at DATETIME NOT NULL,
+    published_at DATETIME
+);
+CREATE INDEX publish_queue_published_at ON publish_queue (published_at);
@@ -0,0 +1,14 @@
+-- Add up migration script here
+CREATE TABLE update_schedule (
+    gallery_id INTEGER PRIMARY KEY NOT NULL,
+    next_check_at DATETIME NOT NULL,
+    -- 连续没有发现变化的检查次数
+    unchanged INTEGER NOT NULL DEFAULT 0,
+    -- 管理员指定的检查间隔，单位为小时，为空时自动计算
+    interval_hours INTEGER,
+    last_checked_at DATETIME
+);
+CREATE INDEX update_schedule_next_check_at ON update_schedule (next_check_at);
+-- 已发布的画廊分散到两周内进行第一次检查，避免同时检查
+INSERT OR IGNORE INTO update_schedule (gallery_id, next_check_at)
+SELECT DISTINCT gallery_id, datetime('now', '+' || (gallery_id % 336) || ' hours') FROM message;
@@ -0,0 +1,18 @@
+-- Add up migration script here
+CREATE TABLE gallery_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    gallery_id INTEGER NOT NULL,
+    -- 标题没有变化时为空
+    old_title TEXT,
+    new_title TEXT,
+    -- JSON 格式，与 gallery.tags 相同
+    added_tags TEXT NOT NULL DEFAULT '',
+    removed_tags TEXT NOT NULL DEFAULT '',
+    -- 页数没有变化时为空
+    old_pages INTEGER,
+    new_pages INTEGER,
+    created_at DATETIME NOT NULL
+);
+CREATE INDEX gallery_history_gallery_id ON gallery_history (gallery_id);
+-- 频道消息被自动转发到讨论组后，在讨论组中的消息 ID
+ALTER TABLE message ADD COLUMN thread_id INTEGER;
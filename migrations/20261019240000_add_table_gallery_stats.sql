@@ -0,0 +1,15 @@
+-- Add up migration script here
+CREATE TABLE gallery_stats (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    gallery_id INTEGER NOT NULL,
+    favorite INTEGER NOT NULL,
+    -- E 站评分，没有评分时为空
+    rating REAL,
+    rating_count INTEGER NOT NULL,
+    -- 本站投票的分数，没有投票时为空
+    score REAL,
+    votes INTEGER NOT NULL,
+    created_at DATETIME NOT NULL
+);
+CREATE INDEX gallery_stats_gallery_id ON gallery_stats (gallery_id, created_at);
+CREATE INDEX gallery_stats_created_at ON gallery_stats (created_at);
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- 图片文件的大小和 ETag，用于检查存储中的文件是否完整，未知时为空
+ALTER TABLE image ADD COLUMN size INTEGER;
+ALTER TABLE image ADD COLUMN etag TEXT;
@@ -0,0 +1,28 @@
+-- Add up migration script here
+-- 图片改为记录存储后端和对象键，公开访问地址在使用时根据配置生成
+ALTER TABLE image ADD COLUMN storage TEXT NOT NULL DEFAULT 's3';
+ALTER TABLE image ADD COLUMN key TEXT NOT NULL DEFAULT '';
+
+-- 早期上传到 telegraph 的图片，地址形如 /file/xxx.jpg
+UPDATE image SET storage = 'telegraph', key = substr(url, 2) WHERE url LIKE '/file/%';
+-- 上传到 s3 的图片，地址形如 https://host/key，其中 host 可能带有路径，无法在这里可靠地拆分，
+-- 会在启动时根据配置中的地址去掉前缀，认不出地址的图片保持 key 为空，继续使用原来的 url
+
+DROP VIEW challenge_view;
+CREATE VIEW challenge_view AS
+SELECT gallery.id,
+       gallery.token,
+       JSON_EXTRACT(gallery.tags, '$.artist[0]') AS artist,
+       page.page,
+       image.id AS image_id,
+       image.storage,
+       image.key,
+       poll.score
+FROM page
+         LEFT JOIN gallery ON gallery.id = page.gallery_id
+         LEFT JOIN image ON image.id = page.image_id
+         LEFT JOIN poll ON poll.gallery_id = gallery.id
+WHERE gallery.pages NOTNULL
+    AND gallery.tags != ""
+    AND image.flag = 0
+	AND JSON_ARRAY_LENGTH(JSON_EXTRACT(gallery.tags, '$.artist')) = 1;
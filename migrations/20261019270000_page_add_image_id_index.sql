@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- 用于查找使用了某张图片的所有页面
+CREATE INDEX page_image_id_idx ON page (image_id);
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE publish_queue ADD COLUMN gallery TEXT;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE message ADD COLUMN created_at DATETIME;
+CREATE INDEX message_created_at_idx ON message (created_at);
@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- 缩略图与图片一样记录存储后端和对象键，公开访问地址在使用时根据配置生成
+-- 对象键会在启动时根据配置中的地址从 url 中拆分出来，认不出地址的缩略图继续使用原来的 url
+ALTER TABLE contact_sheet ADD COLUMN storage TEXT NOT NULL DEFAULT 's3';
+ALTER TABLE contact_sheet ADD COLUMN key TEXT NOT NULL DEFAULT '';
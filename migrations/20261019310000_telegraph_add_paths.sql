@@ -0,0 +1,8 @@
+-- Add up migration script here
+-- 分多篇发布的文章，按顺序记录每一篇的路径，path 列始终为第一篇
+ALTER TABLE telegraph ADD COLUMN paths TEXT NOT NULL DEFAULT '';
+
+UPDATE telegraph
+SET paths = (SELECT json_group_array(substr(value, length('https://telegra.ph/') + 1)) FROM json_each(telegraph.parts))
+WHERE parts LIKE '["https://telegra.ph/%';
+UPDATE telegraph SET paths = json_array(path) WHERE paths = '' AND path != '' AND parts LIKE '["%' AND parts NOT LIKE '%","%';
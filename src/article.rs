@@ -0,0 +1,75 @@
+//! telegraph 文章内容的构建
+//!
+//! 文章使用 telegraph 的 Node 格式构建，文本节点会在发布时由对应的 [`crate::publisher`] 转义
+
+use std::collections::HashMap;
+
+use telegraph_rs::{Node, NodeElement};
+
+/// 文本节点
+pub fn text<S: Into<String>>(s: S) -> Node {
+    Node::Text(s.into())
+}
+
+/// 元素节点
+pub fn element(tag: &str, attrs: &[(&str, &str)], children: Vec<Node>) -> Node {
+    let attrs =
+        attrs.iter().map(|(k, v)| (k.to_string(), Some(v.to_string()))).collect::<HashMap<_, _>>();
+    Node::NodeElement(NodeElement {
+        tag: tag.to_owned(),
+        attrs: (!attrs.is_empty()).then_some(attrs),
+        children: (!children.is_empty()).then_some(children),
+    })
+}
+
+/// 图片
+pub fn img(src: &str) -> Node {
+    element("img", &[("src", src)], vec![])
+}
+
+/// 链接
+pub fn a<S: Into<String>>(href: &str, content: S) -> Node {
+    element("a", &[("href", href)], vec![text(content)])
+}
+
+/// 段落
+pub fn p(children: Vec<Node>) -> Node {
+    element("p", &[], children)
+}
+
+/// 加粗的文字
+pub fn b<S: Into<String>>(content: S) -> Node {
+    element("b", &[], vec![text(content)])
+}
+
+/// 将文章内容按照序列化后的大小拆分为多篇，每个节点不会被拆开
+pub fn split_article(nodes: Vec<Node>, max_size: usize) -> Vec<Vec<Node>> {
+    let mut parts = vec![];
+    let (mut part, mut size) = (vec![], 0);
+    for node in nodes {
+        // 以序列化后的 JSON 大小估算，加上分隔用的逗号
+        let len = serde_json::to_string(&node).unwrap().len() + 1;
+        if size + len > max_size && !part.is_empty() {
+            parts.push(std::mem::take(&mut part));
+            size = 0;
+        }
+        part.push(node);
+        size += len;
+    }
+    parts.push(part);
+    parts
+}
+
+/// 生成第 i 篇文章的导航栏
+pub fn article_nav(urls: &[String], i: usize) -> Node {
+    let mut nav = vec![text(format!("第 {}/{} 篇", i + 1, urls.len()))];
+    if i > 0 {
+        nav.push(text(" | "));
+        nav.push(a(&urls[i - 1], "上一篇"));
+    }
+    if i + 1 < urls.len() {
+        nav.push(text(" | "));
+        nav.push(a(&urls[i + 1], "下一篇"));
+    }
+    p(nav)
+}
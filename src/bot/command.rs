@@ -1,5 +1,6 @@
 use teloxide::utils::command::BotCommands;
 
+use crate::database::ImageFlag;
 use crate::ehentai::EhGalleryUrl;
 
 // NOTE: 此处必须实现 Clone，否则不满足 dptree 的 Injectable 约束
@@ -17,6 +18,52 @@ pub enum AdminCommand {
     ReUpload,
     #[command(description = "检测并补档 80 分以上或最近两个月的本子的预览")]
     ReCheck,
+    #[command(description = "为没有感知哈希的旧图片补充感知哈希")]
+    ReHash,
+    #[command(
+        description = "检查图片是否完整并重新上传损坏的图片，用法：/verify [画廊地址] [deep]，不指定画廊时检查所有画廊，deep 会下载图片校验哈希"
+    )]
+    Verify(String),
+    #[command(
+        description = "将存放在 telegraph 上的旧图片迁移到自己的存储中",
+        rename = "migrate_images"
+    )]
+    MigrateImages,
+    #[command(description = "为已发布但没有缩略图的画廊补充缩略图")]
+    Grid,
+    #[command(description = "列出 telegraph 账号池中的所有账号")]
+    Accounts,
+    #[command(
+        description = "使用当前的模板渲染指定画廊的消息，但不发送",
+        rename = "preview_template"
+    )]
+    PreviewTemplate(EhGalleryUrl),
+    #[command(description = "查看指定画廊的上传规则匹配过程")]
+    Explain(EhGalleryUrl),
+    #[command(description = "查看发布队列")]
+    Queue,
+    #[command(
+        description = "调整画廊在发布队列中的位置，用法：/queue_move <画廊 ID> <位置>",
+        rename = "queue_move",
+        parse_with = "split"
+    )]
+    QueueMove(i32, usize),
+    #[command(
+        description = "立即发布队列中的画廊，用法：/queue_publish <画廊 ID>",
+        rename = "queue_publish"
+    )]
+    QueuePublish(i32),
+    #[command(
+        description = "指定画廊的元数据检查间隔，用法：/update_interval <画廊 ID> <小时数>，小时数为 0 时恢复自动计算",
+        rename = "update_interval",
+        parse_with = "split"
+    )]
+    UpdateInterval(i32, i32),
+    #[command(
+        description = "标记画廊的某一页，用法：/flag <ok|broken|ad> <画廊地址> <页码>",
+        parse_with = "split"
+    )]
+    Flag(ImageFlag, EhGalleryUrl, i32),
 }
 
 #[derive(BotCommands, Clone, PartialEq, Debug)]
@@ -28,6 +75,8 @@ pub enum PublicCommand {
     Update(String),
     #[command(description = "根据 E 站 URL 查询一个指定画廊")]
     Query(EhGalleryUrl),
+    #[command(description = "根据 E 站 URL 查看画廊标题、标签和页数的变化记录")]
+    History(EhGalleryUrl),
     #[command(
         description = "查询从最近 $1 天到 $2 天内的本子排名（$1 < $2）",
         parse_with = "split"
@@ -35,6 +84,8 @@ pub enum PublicCommand {
     Best(u16, u16),
     #[command(description = "想和本 bot 斗斗吗？")]
     Challenge,
+    #[command(description = "回复一张图片，查找它来自哪个画廊")]
+    Source,
     #[command(description = "pong~")]
     Ping,
     #[command(description = "帮助")]
@@ -2,7 +2,7 @@ use std::time::Duration;
 
 use teloxide::prelude::*;
 
-use super::filter::{filter_callbackdata, filter_channel_msg};
+use super::filter::{filter_callbackdata, filter_channel_msg, filter_private_chat};
 use super::handlers::*;
 use super::utils::{ChallengeLocker, ChallengeProvider, RateLimiter};
 use super::Bot;
@@ -22,7 +22,13 @@ pub async fn start_dispatcher(
             Update::filter_message()
                 .branch(admin_command_handler())
                 .branch(public_command_handler(config.clone()))
-                .branch(filter_channel_msg().endpoint(custom_pool_sender)),
+                .branch(review_note_handler())
+                .branch(filter_channel_msg().endpoint(custom_pool_sender))
+                .branch(
+                    filter_private_chat()
+                        .filter(|message: Message| image_file_id(&message).is_some())
+                        .endpoint(image_source_handler),
+                ),
         )
         .branch(
             Update::filter_callback_query()
@@ -36,7 +42,7 @@ pub async fn start_dispatcher(
 
     let challenge_locker = ChallengeLocker::new();
 
-    let challenge_provider = ChallengeProvider::new();
+    let challenge_provider = ChallengeProvider::new(ehentai.storage().clone());
 
     let scheduler = Scheduler::new(bot.clone());
 
@@ -11,15 +11,20 @@ where
     Output: Send + Sync + 'static,
 {
     dptree::filter_async(|message: Message, bot: Bot, cfg: Config| async move {
-        bot.get_chat_member(cfg.telegram.group_id, message.from().unwrap().id)
-            .await
-            .map(|member| {
-                matches!(member.kind, ChatMemberKind::Administrator(_) | ChatMemberKind::Owner(_))
-            })
-            .unwrap_or_default()
+        is_admin(&bot, &cfg, message.from().unwrap().id).await
     })
 }
 
+/// 检查用户是否为主频道讨论组的管理员
+pub async fn is_admin(bot: &Bot, cfg: &Config, user: UserId) -> bool {
+    bot.get_chat_member(cfg.telegram.main.group_id, user)
+        .await
+        .map(|member| {
+            matches!(member.kind, ChatMemberKind::Administrator(_) | ChatMemberKind::Owner(_))
+        })
+        .unwrap_or_default()
+}
+
 pub fn filter_channel_msg<Output>() -> Handler<'static, DependencyMap, Output, DpHandlerDescription>
 where
     Output: Send + Sync + 'static,
@@ -27,7 +32,7 @@ where
     dptree::filter(|message: Message, cfg: Config| {
         message.from().map(|u| u.id.0 == 777000).unwrap_or_default()
             && message.text().map(|s| s.contains("原始地址")).unwrap_or_default()
-            && cfg.telegram.group_id == message.chat.id
+            && cfg.telegram.is_group(message.chat.id)
     })
 }
 
@@ -40,6 +45,7 @@ where
     })
 }
 
+#[allow(dead_code)]
 pub fn filter_member<C, Output>(
     chat_id: C,
     status: ChatMemberKind,
@@ -2,23 +2,35 @@ use anyhow::{Context, Result};
 use teloxide::dispatching::DpHandlerDescription;
 use teloxide::dptree::case;
 use teloxide::prelude::*;
-use teloxide::utils::html::{link, user_mention};
+use teloxide::utils::html::{escape, link, user_mention};
 use tracing::info;
 
 use super::utils::gallery_preview_url;
-use crate::bot::handlers::{cmd_best_keyboard, cmd_best_text, poll_keyboard};
+use crate::bot::filter::is_admin;
+use crate::bot::handlers::{cmd_best_keyboard, cmd_best_text, flag_keyboard, poll_keyboard};
 use crate::bot::utils::{CallbackData, ChallengeLocker, RateLimiter};
 use crate::bot::Bot;
 use crate::config::Config;
-use crate::database::{ChallengeHistory, GalleryEntity, PollEntity, VoteEntity};
+use crate::database::{
+    ChallengeHistory, GalleryEntity, GalleryRelationEntity, ImageFlag, PageEntity, PollEntity,
+    RelationKind, ReviewEntity, ReviewStatus, VoteEntity,
+};
 use crate::ehentai::GalleryInfo;
 use crate::tags::EhTagTransDB;
+use crate::uploader::ExloliUploader;
 
 pub fn callback_query_handler() -> Handler<'static, DependencyMap, Result<()>, DpHandlerDescription>
 {
     dptree::entry()
         .branch(case![CallbackData::VoteForPoll(poll, option)].endpoint(callback_vote_for_poll))
         .branch(case![CallbackData::Challenge(id, artist)].endpoint(callback_challenge))
+        .branch(
+            case![CallbackData::DuplicateDecision(gallery, related, kind)]
+                .endpoint(callback_duplicate),
+        )
+        .branch(case![CallbackData::FlagImage(image, flag)].endpoint(callback_flag_image))
+        .branch(case![CallbackData::Review(gallery, status)].endpoint(callback_review))
+        .branch(case![CallbackData::ReviewNote(gallery)].endpoint(callback_review_note))
         .endpoint(callback_change_page)
 }
 
@@ -36,7 +48,8 @@ async fn callback_challenge(
     if let Some((gallery, page, answer)) = locker.get_challenge(id) {
         let success = answer == artist;
         let gallery_entity = GalleryEntity::get(gallery).await?.context("找不到画廊")?;
-        let preview = gallery_preview_url(cfg.telegram.channel_id, gallery).await?;
+        let channel = cfg.telegram.channel_of_group(message.chat.id);
+        let preview = gallery_preview_url(channel, gallery).await?;
         let poll = PollEntity::get_by_gallery(gallery).await?.context("找不到投票")?;
         ChallengeHistory::create(query.from.id.0 as i64, gallery, page, success, message.chat.id.0)
             .await?;
@@ -56,8 +69,150 @@ async fn callback_challenge(
             "{mention} {result}，答案是 {artist}（{answer}）\n回答情况：{stat_success}/{stat_total}\n地址：{url}\n预览：{preview}\n评分：{score:.2}（{rank:.2}%）",
         );
 
-        bot.edit_message_caption(message.chat.id, message.id).caption(text).await?;
+        let mut request = bot.edit_message_caption(message.chat.id, message.id).caption(text);
+        // 答题结束后，允许管理员直接标记这张图片
+        if let Some(page) = PageEntity::get(gallery, page).await? {
+            request = request.reply_markup(flag_keyboard(page.image_id));
+        }
+        request.await?;
+    }
+    Ok(())
+}
+
+async fn callback_flag_image(
+    bot: Bot,
+    query: CallbackQuery,
+    cfg: Config,
+    uploader: ExloliUploader,
+    (image, flag): (u32, ImageFlag),
+) -> Result<()> {
+    if !is_admin(&bot, &cfg, query.from.id).await {
+        bot.answer_callback_query(query.id).text("只有管理员可以操作").show_alert(true).await?;
+        return Ok(());
+    }
+    info!("{}: 标记图片 {} = {}", query.from.id, image, flag.as_str());
+    bot.answer_callback_query(query.id).text("处理中").await?;
+    uploader.flag_image(image, flag).await?;
+    Ok(())
+}
+
+async fn callback_duplicate(
+    bot: Bot,
+    query: CallbackQuery,
+    cfg: Config,
+    uploader: ExloliUploader,
+    (gallery, related, kind): (i32, i32, RelationKind),
+) -> Result<()> {
+    if !is_admin(&bot, &cfg, query.from.id).await {
+        bot.answer_callback_query(query.id).text("只有管理员可以操作").show_alert(true).await?;
+        return Ok(());
+    }
+    let relation = GalleryRelationEntity::get(gallery).await?.context("找不到画廊关系")?;
+    if relation.kind != RelationKind::Pending {
+        bot.answer_callback_query(query.id).text("该画廊已经处理过了").await?;
+        return Ok(());
+    }
+
+    info!("{}: 重复画廊 {} -> {} = {:?}", query.from.id, gallery, related, kind);
+    GalleryRelationEntity::update_kind(gallery, related, kind).await?;
+    bot.answer_callback_query(query.id).text("处理中").await?;
+
+    if let Some(message) = query.message {
+        let decision = match kind {
+            RelationKind::Version => "作为新版本发布",
+            RelationKind::Distinct => "单独发布",
+            _ => "跳过",
+        };
+        let text = format!(
+            "{}\n\n{} 选择了：{}",
+            escape(message.text().unwrap_or_default()),
+            user_mention(query.from.id.0 as i64, &query.from.full_name()),
+            decision
+        );
+        bot.edit_message_text(message.chat.id, message.id, text)
+            .disable_web_page_preview(true)
+            .await?;
+    }
+
+    if kind != RelationKind::Duplicate {
+        let entity = GalleryEntity::get(gallery).await?.context("找不到画廊")?;
+        uploader.try_upload(&entity.url(), false).await?;
+    }
+
+    Ok(())
+}
+
+async fn callback_review(
+    bot: Bot,
+    query: CallbackQuery,
+    cfg: Config,
+    uploader: ExloliUploader,
+    (gallery, status): (i32, ReviewStatus),
+) -> Result<()> {
+    if !is_admin(&bot, &cfg, query.from.id).await {
+        bot.answer_callback_query(query.id).text("只有管理员可以操作").show_alert(true).await?;
+        return Ok(());
+    }
+    if ReviewEntity::decide(gallery, status, None).await?.rows_affected() == 0 {
+        bot.answer_callback_query(query.id).text("该画廊已经处理过了").await?;
+        return Ok(());
+    }
+
+    info!("{}: 审核画廊 {} = {:?}", query.from.id, gallery, status);
+    bot.answer_callback_query(query.id).text("处理中").await?;
+
+    if let Some(message) = query.message {
+        let decision = match status {
+            ReviewStatus::Approved => "批准",
+            _ => "拒绝",
+        };
+        let decided = format!(
+            "\n\n{} 选择了：{}",
+            user_mention(query.from.id.0 as i64, &query.from.full_name()),
+            decision
+        );
+        // 审核消息可能是图片消息，也可能是纯文本消息
+        match message.caption() {
+            Some(caption) => {
+                bot.edit_message_caption(message.chat.id, message.id)
+                    .caption(format!("{}{}", escape(caption), decided))
+                    .await?;
+            }
+            None => {
+                bot.edit_message_text(
+                    message.chat.id,
+                    message.id,
+                    format!("{}{}", escape(message.text().unwrap_or_default()), decided),
+                )
+                .disable_web_page_preview(true)
+                .await?;
+            }
+        }
+    }
+
+    if status == ReviewStatus::Approved {
+        let entity = GalleryEntity::get(gallery).await?.context("找不到画廊")?;
+        uploader.approve(&entity.url()).await?;
     }
+
+    Ok(())
+}
+
+async fn callback_review_note(
+    bot: Bot,
+    query: CallbackQuery,
+    cfg: Config,
+    gallery: i32,
+) -> Result<()> {
+    if !is_admin(&bot, &cfg, query.from.id).await {
+        bot.answer_callback_query(query.id).text("只有管理员可以操作").show_alert(true).await?;
+        return Ok(());
+    }
+    info!("{}: 附言后批准 {}", query.from.id, gallery);
+    bot.answer_callback_query(query.id)
+        .text("请回复这条审核消息，回复的内容会作为附言展示在频道消息中，并批准发布")
+        .show_alert(true)
+        .await?;
     Ok(())
 }
 
@@ -110,15 +265,15 @@ async fn callback_change_page(
         CallbackData::NextPage(from, to, offset) => (from, to, offset + 1),
         _ => unreachable!(),
     };
-    let text = cmd_best_text(from, to, offset, cfg.telegram.channel_id).await?;
+    let message = query.message.context("消息过旧")?;
+    let channel = cfg.telegram.channel_of_group(message.chat.id);
+    let text = cmd_best_text(from, to, offset, channel).await?;
     let keyboard = cmd_best_keyboard(from, to, offset);
 
-    if let Some(message) = query.message {
-        bot.edit_message_text(message.chat.id, message.id, text)
-            .reply_markup(keyboard)
-            .disable_web_page_preview(true)
-            .await?;
-    }
+    bot.edit_message_text(message.chat.id, message.id, text)
+        .reply_markup(keyboard)
+        .disable_web_page_preview(true)
+        .await?;
 
     Ok(())
 }
@@ -3,13 +3,15 @@ use teloxide::dispatching::DpHandlerDescription;
 use teloxide::dptree::case;
 use teloxide::prelude::*;
 use teloxide::types::MessageId;
+use teloxide::utils::html::{escape, link};
 use tracing::info;
 
 use crate::bot::command::AdminCommand;
 use crate::bot::filter::filter_admin_msg;
 use crate::bot::Bot;
-use crate::database::{GalleryEntity, MessageEntity};
-use crate::ehentai::EhGalleryUrl;
+use crate::config::Config;
+use crate::database::{GalleryEntity, ImageFlag, MessageEntity, PageEntity, QueueEntity};
+use crate::ehentai::{EhGalleryUrl, GalleryInfo};
 use crate::uploader::ExloliUploader;
 use crate::{reply_to, try_with_reply};
 
@@ -22,6 +24,18 @@ pub fn admin_command_handler() -> Handler<'static, DependencyMap, Result<()>, Dp
         .branch(case![AdminCommand::Erase].endpoint(cmd_delete))
         .branch(case![AdminCommand::ReCheck].endpoint(cmd_recheck))
         .branch(case![AdminCommand::ReUpload].endpoint(cmd_reupload))
+        .branch(case![AdminCommand::ReHash].endpoint(cmd_rehash))
+        .branch(case![AdminCommand::Verify(args)].endpoint(cmd_verify))
+        .branch(case![AdminCommand::MigrateImages].endpoint(cmd_migrate_images))
+        .branch(case![AdminCommand::Grid].endpoint(cmd_grid))
+        .branch(case![AdminCommand::Accounts].endpoint(cmd_accounts))
+        .branch(case![AdminCommand::PreviewTemplate(gallery)].endpoint(cmd_preview_template))
+        .branch(case![AdminCommand::Explain(gallery)].endpoint(cmd_explain))
+        .branch(case![AdminCommand::Queue].endpoint(cmd_queue))
+        .branch(case![AdminCommand::QueueMove(gallery, position)].endpoint(cmd_queue_move))
+        .branch(case![AdminCommand::QueuePublish(gallery)].endpoint(cmd_queue_publish))
+        .branch(case![AdminCommand::UpdateInterval(gallery, hours)].endpoint(cmd_update_interval))
+        .branch(case![AdminCommand::Flag(flag, gallery, page)].endpoint(cmd_flag))
 }
 
 // TODO: 该功能需要移除
@@ -37,6 +51,169 @@ async fn cmd_recheck(bot: Bot, msg: Message, uploader: ExloliUploader) -> Result
     Ok(())
 }
 
+async fn cmd_rehash(bot: Bot, msg: Message, uploader: ExloliUploader) -> Result<()> {
+    info!("{}: /rehash", msg.from().unwrap().id);
+    try_with_reply!(bot, msg, uploader.rehash().await);
+    Ok(())
+}
+
+async fn cmd_verify(bot: Bot, msg: Message, uploader: ExloliUploader, args: String) -> Result<()> {
+    info!("{}: /verify {}", msg.from().unwrap().id, args);
+    let mut gallery = None;
+    let mut deep = false;
+    for arg in args.split_whitespace() {
+        match arg {
+            "deep" => deep = true,
+            url => match url.parse::<EhGalleryUrl>() {
+                Ok(url) => gallery = Some(url.id()),
+                Err(_) => {
+                    reply_to!(bot, msg, "无效的画廊地址").await?;
+                    return Ok(());
+                }
+            },
+        }
+    }
+    let reply = reply_to!(bot, msg, "检查中……").await?;
+    let text = match uploader.verify_images(gallery, deep, Some((msg.chat.id, reply.id))).await {
+        Ok(stats) => format!("检查完成\n{}", stats),
+        Err(err) => format!("执行失败：{}", escape(&err.to_string())),
+    };
+    bot.edit_message_text(msg.chat.id, reply.id, text).await?;
+    Ok(())
+}
+
+async fn cmd_migrate_images(bot: Bot, msg: Message, uploader: ExloliUploader) -> Result<()> {
+    info!("{}: /migrate_images", msg.from().unwrap().id);
+    let reply = reply_to!(bot, msg, "迁移中……").await?;
+    let text = match uploader.migrate_legacy_images(Some((msg.chat.id, reply.id))).await {
+        Ok(stats) => format!("迁移完成\n{}", stats),
+        Err(err) => format!("执行失败：{}", escape(&err.to_string())),
+    };
+    bot.edit_message_text(msg.chat.id, reply.id, text).await?;
+    Ok(())
+}
+
+async fn cmd_grid(bot: Bot, msg: Message, uploader: ExloliUploader) -> Result<()> {
+    info!("{}: /grid", msg.from().unwrap().id);
+    try_with_reply!(bot, msg, uploader.backfill_contact_sheets().await);
+    Ok(())
+}
+
+async fn cmd_accounts(bot: Bot, msg: Message, uploader: ExloliUploader) -> Result<()> {
+    info!("{}: /accounts", msg.from().unwrap().id);
+    let mut text = String::from("telegraph 账号：\n");
+    for (account, page_count) in uploader.telegraph_accounts().await? {
+        let page_count = page_count.map(|n| n.to_string()).unwrap_or_else(|| "未知".to_string());
+        let status = if account.retired { "已停用" } else { "使用中" };
+        text.push_str(&format!(
+            "\n#{} {}，文章数：{}，连续失败：{}",
+            account.id, status, page_count, account.failures
+        ));
+    }
+    reply_to!(bot, msg, text).await?;
+    Ok(())
+}
+
+async fn cmd_preview_template(
+    bot: Bot,
+    msg: Message,
+    uploader: ExloliUploader,
+    gallery: EhGalleryUrl,
+) -> Result<()> {
+    info!("{}: /preview_template {}", msg.from().unwrap().id, gallery);
+    let text = uploader.preview_message(&gallery).await?;
+    reply_to!(bot, msg, text).disable_web_page_preview(true).await?;
+    Ok(())
+}
+
+async fn cmd_explain(
+    bot: Bot,
+    msg: Message,
+    uploader: ExloliUploader,
+    gallery: EhGalleryUrl,
+) -> Result<()> {
+    info!("{}: /explain {}", msg.from().unwrap().id, gallery);
+    let text = uploader.explain(&gallery).await?;
+    reply_to!(bot, msg, escape(&text)).await?;
+    Ok(())
+}
+
+async fn cmd_queue(bot: Bot, msg: Message) -> Result<()> {
+    info!("{}: /queue", msg.from().unwrap().id);
+    let queue = QueueEntity::list_pending().await?;
+    if queue.is_empty() {
+        reply_to!(bot, msg, "发布队列为空").await?;
+        return Ok(());
+    }
+    let mut text = format!("发布队列中共有 {} 个画廊：\n", queue.len());
+    for (i, item) in queue.iter().enumerate() {
+        let gallery = GalleryEntity::get(item.gallery_id).await?.context("找不到画廊")?;
+        text.push_str(&format!(
+            "\n{}. <code>{}</code> {}",
+            i + 1,
+            gallery.id,
+            link(&gallery.url().url(), &gallery.title_jp())
+        ));
+    }
+    reply_to!(bot, msg, text).disable_web_page_preview(true).await?;
+    Ok(())
+}
+
+async fn cmd_queue_move(
+    bot: Bot,
+    msg: Message,
+    uploader: ExloliUploader,
+    (gallery, position): (i32, usize),
+) -> Result<()> {
+    info!("{}: /queue_move {} {}", msg.from().unwrap().id, gallery, position);
+    try_with_reply!(bot, msg, uploader.move_in_queue(gallery, position).await);
+    Ok(())
+}
+
+async fn cmd_queue_publish(
+    bot: Bot,
+    msg: Message,
+    uploader: ExloliUploader,
+    gallery: i32,
+) -> Result<()> {
+    info!("{}: /queue_publish {}", msg.from().unwrap().id, gallery);
+    try_with_reply!(bot, msg, uploader.release(gallery).await);
+    Ok(())
+}
+
+async fn cmd_update_interval(
+    bot: Bot,
+    msg: Message,
+    uploader: ExloliUploader,
+    (gallery, hours): (i32, i32),
+) -> Result<()> {
+    info!("{}: /update_interval {} {}", msg.from().unwrap().id, gallery, hours);
+    let hours = (hours > 0).then_some(hours);
+    let text = match (uploader.set_update_interval(gallery, hours).await, hours) {
+        (Ok(next), Some(hours)) => {
+            format!("已设置为每 {} 小时检查一次，下次检查：{} UTC", hours, next.format("%F %R"))
+        }
+        (Ok(next), None) => {
+            format!("已恢复自动计算检查间隔，下次检查：{} UTC", next.format("%F %R"))
+        }
+        (Err(err), _) => format!("执行失败：{}", escape(&err.to_string())),
+    };
+    reply_to!(bot, msg, text).await?;
+    Ok(())
+}
+
+async fn cmd_flag(
+    bot: Bot,
+    msg: Message,
+    uploader: ExloliUploader,
+    (flag, gallery, page): (ImageFlag, EhGalleryUrl, i32),
+) -> Result<()> {
+    info!("{}: /flag {} {} {}", msg.from().unwrap().id, flag.as_str(), gallery, page);
+    let page = PageEntity::get(gallery.id(), page).await?.context("找不到该页面")?;
+    try_with_reply!(bot, msg, uploader.flag_image(page.image_id, flag).await);
+    Ok(())
+}
+
 async fn cmd_upload(
     bot: Bot,
     msg: Message,
@@ -48,14 +225,18 @@ async fn cmd_upload(
     Ok(())
 }
 
-async fn cmd_delete(bot: Bot, msg: Message, command: AdminCommand) -> Result<()> {
+async fn cmd_delete(bot: Bot, msg: Message, cfg: Config, command: AdminCommand) -> Result<()> {
     info!("{}: /delete", msg.from().unwrap().id);
     let reply_to = msg.reply_to_message().context("没有回复消息")?;
 
     let channel = reply_to.forward_from_chat().context("该消息没有回复画廊")?;
     let channel_msg = reply_to.forward_from_message_id().context("获取转发来源失败")?;
 
-    let msg_entity = MessageEntity::get(channel_msg).await?.unwrap();
+    let key = match cfg.telegram.channel_of_chat(channel) {
+        Some(channel) => channel.key(),
+        None => channel.id.to_string(),
+    };
+    let msg_entity = MessageEntity::get(&key, channel_msg).await?.context("找不到消息")?;
 
     bot.delete_message(reply_to.chat.id, reply_to.id).await?;
     bot.delete_message(channel.id, MessageId(msg_entity.id)).await?;
@@ -64,7 +245,7 @@ async fn cmd_delete(bot: Bot, msg: Message, command: AdminCommand) -> Result<()>
         GalleryEntity::update_deleted(msg_entity.gallery_id, true).await?;
     } else {
         GalleryEntity::delete(msg_entity.gallery_id).await?;
-        MessageEntity::delete(channel_msg).await?;
+        MessageEntity::delete(&key, channel_msg).await?;
     }
 
     Ok(())
@@ -6,33 +6,39 @@ use teloxide::dptree::case;
 use teloxide::prelude::*;
 use teloxide::types::InputFile;
 use teloxide::utils::command::BotCommands;
-use teloxide::utils::html::escape;
+use teloxide::utils::html::{escape, link};
 use tracing::info;
 
 use crate::bot::command::{AdminCommand, PublicCommand};
 use crate::bot::handlers::{
-    cmd_best_keyboard, cmd_best_text, cmd_challenge_keyboard, gallery_preview_url,
+    cmd_best_keyboard, cmd_best_text, cmd_challenge_keyboard, gallery_preview_url, image_file_id,
+    image_source_text,
 };
 use crate::bot::scheduler::Scheduler;
 use crate::bot::utils::{ChallengeLocker, ChallengeProvider};
 use crate::bot::Bot;
 use crate::config::Config;
-use crate::database::{GalleryEntity, MessageEntity, PollEntity};
+use crate::database::{GalleryEntity, GalleryHistoryEntity, MessageEntity, PollEntity};
 use crate::ehentai::{EhGalleryUrl, GalleryInfo};
 use crate::tags::EhTagTransDB;
 use crate::uploader::ExloliUploader;
 use crate::{reply_to, try_with_reply};
 
+/// /history 最多展示的记录数量
+const MAX_HISTORY: usize = 20;
+
 pub fn public_command_handler(
     _config: Config,
 ) -> Handler<'static, DependencyMap, Result<()>, DpHandlerDescription> {
     teloxide::filter_command::<PublicCommand, _>()
         .branch(case![PublicCommand::Query(gallery)].endpoint(cmd_query))
+        .branch(case![PublicCommand::History(gallery)].endpoint(cmd_history))
         .branch(case![PublicCommand::Ping].endpoint(cmd_ping))
         .branch(case![PublicCommand::Update(url)].endpoint(cmd_update))
         .branch(case![PublicCommand::Best(from, to)].endpoint(cmd_best))
         .branch(case![PublicCommand::Challenge].endpoint(cmd_challenge))
         .branch(case![PublicCommand::Upload(gallery)].endpoint(cmd_upload))
+        .branch(case![PublicCommand::Source].endpoint(cmd_source))
         .branch(case![PublicCommand::Help].endpoint(cmd_help))
 }
 
@@ -63,6 +69,7 @@ async fn cmd_upload(
 async fn cmd_challenge(
     bot: Bot,
     msg: Message,
+    uploader: ExloliUploader,
     trans: EhTagTransDB,
     locker: ChallengeLocker,
     scheduler: Scheduler,
@@ -72,11 +79,10 @@ async fn cmd_challenge(
     let mut challenge = challange_provider.get_challenge().await.unwrap();
     let answer = challenge[0].clone();
     challenge.shuffle(&mut thread_rng());
-    let url = format!("https://telegra.ph{}", answer.url);
     let id = locker.add_challenge(answer.id, answer.page, answer.artist.clone());
     let keyboard = cmd_challenge_keyboard(id, &challenge, &trans);
     let reply = bot
-        .send_photo(msg.chat.id, InputFile::url(url.parse()?))
+        .send_photo(msg.chat.id, InputFile::url(answer.url(uploader.storage()).parse()?))
         .caption("上述图片来自下列哪位作者的本子？")
         .reply_markup(keyboard)
         .reply_to_message_id(msg.id)
@@ -88,6 +94,19 @@ async fn cmd_challenge(
     Ok(())
 }
 
+async fn cmd_source(bot: Bot, msg: Message, cfg: Config, scheduler: Scheduler) -> Result<()> {
+    info!("{}: /source", msg.from().unwrap().id);
+    let file_id =
+        msg.reply_to_message().and_then(image_file_id).context("请回复一张图片或图片文件")?;
+    let text = image_source_text(&bot, &file_id, &cfg).await?;
+    let reply = reply_to!(bot, msg, text).disable_web_page_preview(true).await?;
+    if !msg.chat.is_private() {
+        scheduler.delete_msg(msg.chat.id, msg.id, 120);
+        scheduler.delete_msg(msg.chat.id, reply.id, 120);
+    }
+    Ok(())
+}
+
 async fn cmd_best(
     bot: Bot,
     msg: Message,
@@ -96,7 +115,8 @@ async fn cmd_best(
     scheduler: Scheduler,
 ) -> Result<()> {
     info!("{}: /best {} {}", msg.from().unwrap().id, end, start);
-    let text = cmd_best_text(start as i32, end as i32, 0, cfg.telegram.channel_id).await?;
+    let channel = cfg.telegram.channel_of_group(msg.chat.id);
+    let text = cmd_best_text(start as i32, end as i32, 0, channel).await?;
     let keyboard = cmd_best_keyboard(start as i32, end as i32, 0);
     let reply =
         reply_to!(bot, msg, text).reply_markup(keyboard).disable_web_page_preview(true).await?;
@@ -107,20 +127,30 @@ async fn cmd_best(
     Ok(())
 }
 
-async fn cmd_update(bot: Bot, msg: Message, uploader: ExloliUploader, url: String) -> Result<()> {
+async fn cmd_update(
+    bot: Bot,
+    msg: Message,
+    uploader: ExloliUploader,
+    cfg: Config,
+    url: String,
+) -> Result<()> {
     info!("{}: /update {}", msg.from().unwrap().id, url);
-    let msg_id = if url.is_empty() {
+    let (channel, msg_id) = if url.is_empty() {
         msg.reply_to_message()
-            .and_then(|msg| msg.forward_from_message_id())
+            .and_then(|msg| {
+                let chat = msg.forward_from_chat()?;
+                let channel = match cfg.telegram.channel_of_chat(chat) {
+                    Some(channel) => channel.key(),
+                    None => chat.id.to_string(),
+                };
+                Some((channel, msg.forward_from_message_id()?))
+            })
             .ok_or(anyhow!("Invalid URL"))?
     } else {
-        Url::parse(&url)?
-            .path_segments()
-            .and_then(|p| p.last())
-            .and_then(|id| id.parse::<i32>().ok())
-            .ok_or(anyhow!("Invalid URL"))?
+        parse_message_url(&url).ok_or(anyhow!("Invalid URL"))?
     };
-    let msg_entity = MessageEntity::get(msg_id).await?.ok_or(anyhow!("Message not found"))?;
+    let msg_entity =
+        MessageEntity::get(&channel, msg_id).await?.ok_or(anyhow!("Message not found"))?;
     let gl_entity =
         GalleryEntity::get(msg_entity.gallery_id).await?.ok_or(anyhow!("Gallery not found"))?;
 
@@ -150,7 +180,8 @@ async fn cmd_query(bot: Bot, msg: Message, cfg: Config, gallery: EhGalleryUrl) -
     match GalleryEntity::get(gallery.id()).await? {
         Some(gallery) => {
             let poll = PollEntity::get_by_gallery(gallery.id).await?.context("找不到投票")?;
-            let preview = gallery_preview_url(cfg.telegram.channel_id, gallery.id).await?;
+            let channel = cfg.telegram.channel_of_group(msg.chat.id);
+            let preview = gallery_preview_url(channel, gallery.id).await?;
             let url = gallery.url().url();
             reply_to!(
                 bot,
@@ -169,3 +200,42 @@ async fn cmd_query(bot: Bot, msg: Message, cfg: Config, gallery: EhGalleryUrl) -
     }
     Ok(())
 }
+
+async fn cmd_history(bot: Bot, msg: Message, gallery: EhGalleryUrl) -> Result<()> {
+    info!("{}: /history {}", msg.from().unwrap().id, gallery);
+    let entity = match GalleryEntity::get(gallery.id()).await? {
+        Some(v) => v,
+        None => {
+            reply_to!(bot, msg, "未找到").await?;
+            return Ok(());
+        }
+    };
+    let history = GalleryHistoryEntity::list(gallery.id()).await?;
+    let mut text = format!("{} 的变化记录", link(&entity.url().url(), &entity.title_jp()));
+    if history.is_empty() {
+        text.push_str("：无");
+    }
+    // 只展示最近的记录，避免超出消息长度上限
+    for item in history.iter().rev().take(MAX_HISTORY).rev() {
+        text.push_str(&format!(
+            "\n\n<b>{}</b>\n{}",
+            item.created_at.format("%Y-%m-%d %H:%M"),
+            escape(&item.to_string())
+        ));
+    }
+    reply_to!(bot, msg, text).disable_web_page_preview(true).await?;
+    Ok(())
+}
+
+/// 解析频道消息链接，返回数据库中记录的频道 ID 和消息 ID
+///
+/// 支持 `https://t.me/c/<id>/<msg>` 和 `https://t.me/<username>/<msg>` 两种格式
+fn parse_message_url(url: &str) -> Option<(String, i32)> {
+    let url = Url::parse(url).ok()?;
+    let segments = url.path_segments()?.collect::<Vec<_>>();
+    match segments.as_slice() {
+        ["c", id, msg, ..] => Some((format!("-100{id}"), msg.parse().ok()?)),
+        [name, msg, ..] => Some((format!("@{name}"), msg.parse().ok()?)),
+        _ => None,
+    }
+}
@@ -4,14 +4,21 @@ use tracing::info;
 
 use crate::bot::handlers::utils;
 use crate::bot::Bot;
-use crate::database::{GalleryEntity, PollEntity};
+use crate::config::Config;
+use crate::database::{GalleryEntity, MessageEntity, PollEntity};
 use crate::reply_to;
 
-pub async fn custom_pool_sender(bot: Bot, message: Message) -> Result<()> {
+pub async fn custom_pool_sender(bot: Bot, message: Message, cfg: Config) -> Result<()> {
     info!("频道消息更新，发送投票");
 
     let msg_id = message.forward_from_message_id().context("找不到消息")?;
-    let gallery = GalleryEntity::get_by_msg(msg_id).await?.context("找不到画廊")?;
+    // 自动转发的消息来自讨论组所关联的频道
+    let channel = message
+        .forward_from_chat()
+        .and_then(|chat| cfg.telegram.channel_of_chat(chat))
+        .unwrap_or_else(|| cfg.telegram.channel_of_group(message.chat.id));
+    let gallery = GalleryEntity::get_by_msg(&channel.key(), msg_id).await?.context("找不到画廊")?;
+    MessageEntity::set_thread(&channel.key(), msg_id, message.id.0).await?;
 
     // FIXME: 此处如果父画廊还没有记录，则无法找到投票，应该改成不断向 E 站请求父画廊直到有父画廊存在投票或者没有父画廊为止
     // 对于投票的 ID，如果该画廊有投票，则使用该画廊的投票 ID
@@ -0,0 +1,81 @@
+use anyhow::{Context, Result};
+use teloxide::net::Download;
+use teloxide::prelude::*;
+use teloxide::utils::html::link;
+use tracing::info;
+
+use crate::bot::handlers::gallery_preview_url;
+use crate::bot::Bot;
+use crate::config::Config;
+use crate::database::{GalleryEntity, ImageEntity, PageEntity, PollEntity};
+use crate::ehentai::GalleryInfo;
+use crate::reply_to;
+use crate::utils::imagehash::dhash;
+
+/// 搜索时允许的最大汉明距离，感知哈希按 16 位分段建立索引，超过 3 时会漏掉部分结果
+const MAX_DISTANCE: u32 = 3;
+/// 最多返回多少条结果
+const MAX_RESULTS: usize = 10;
+
+/// 私聊中直接发送图片时，查找图片来源
+pub async fn image_source_handler(bot: Bot, msg: Message, cfg: Config) -> Result<()> {
+    info!("{}: 查找图片来源", msg.from().unwrap().id);
+    let file_id = image_file_id(&msg).context("找不到图片")?;
+    let text = image_source_text(&bot, &file_id, &cfg).await?;
+    reply_to!(bot, msg, text).disable_web_page_preview(true).await?;
+    Ok(())
+}
+
+/// 获取消息中的图片的文件 ID，图片可以是照片，也可以是图片格式的文件
+pub fn image_file_id(msg: &Message) -> Option<String> {
+    if let Some(photo) = msg.photo().and_then(|p| p.last()) {
+        return Some(photo.file.id.clone());
+    }
+    let document = msg.document()?;
+    match &document.mime_type {
+        Some(mime) if mime.type_() == "image" => Some(document.file.id.clone()),
+        _ => None,
+    }
+}
+
+/// 下载图片并根据感知哈希在本地数据库中查找相似的图片，返回可供发送的消息正文
+pub async fn image_source_text(bot: &Bot, file_id: &str, cfg: &Config) -> Result<String> {
+    let file = bot.get_file(file_id).await?;
+    let mut data = vec![];
+    bot.download_file(&file.path, &mut data).await?;
+    let phash = dhash(&data)?;
+
+    let mut lines = vec![];
+    'outer: for (image, distance) in ImageEntity::search_by_phash(phash, MAX_DISTANCE).await? {
+        let similarity = (64 - distance) as f32 / 64. * 100.;
+        for page in PageEntity::get_by_image(image.id).await? {
+            let gallery = match GalleryEntity::get(page.gallery_id).await? {
+                Some(v) => v,
+                None => continue,
+            };
+            let preview = gallery_preview_url(&cfg.telegram.main, gallery.id)
+                .await
+                .unwrap_or_else(|_| gallery.url().url());
+            let score = match PollEntity::get_by_gallery(gallery.id).await? {
+                Some(poll) => format!("{:.2}", poll.score * 100.),
+                None => "无".to_string(),
+            };
+            lines.push(format!(
+                "{}\n第 {} 页，相似度 {:.0}%，评分 {}",
+                link(&preview, &gallery.title_jp()),
+                page.page,
+                similarity,
+                score,
+            ));
+            if lines.len() >= MAX_RESULTS {
+                break 'outer;
+            }
+        }
+    }
+
+    if lines.is_empty() {
+        Ok("没有找到相似的图片".to_string())
+    } else {
+        Ok(format!("找到以下相似图片：\n\n{}", lines.join("\n\n")))
+    }
+}
@@ -2,14 +2,18 @@ mod callback_query;
 mod command_admin;
 mod command_public;
 mod custom_poll;
+mod image_source;
 mod join_request;
+mod review_note;
 mod utils;
 
 pub use callback_query::*;
 pub use command_admin::*;
 pub use command_public::*;
 pub use custom_poll::*;
+pub use image_source::*;
 pub use join_request::*;
+pub use review_note::*;
 pub use utils::*;
 
 #[macro_export]
@@ -0,0 +1,49 @@
+use anyhow::{Context, Result};
+use teloxide::dispatching::DpHandlerDescription;
+use teloxide::prelude::*;
+use teloxide::types::MessageId;
+use tracing::info;
+
+use crate::bot::filter::filter_admin_msg;
+use crate::bot::Bot;
+use crate::config::Config;
+use crate::database::{GalleryEntity, ReviewEntity, ReviewStatus};
+use crate::ehentai::GalleryInfo;
+use crate::reply_to;
+use crate::uploader::ExloliUploader;
+
+/// 管理员在讨论组中回复等待中的审核消息时，将回复内容作为附言并批准发布
+pub fn review_note_handler() -> Handler<'static, DependencyMap, Result<()>, DpHandlerDescription> {
+    dptree::filter_map_async(|message: Message, cfg: Config| async move {
+        if message.chat.id != cfg.telegram.main.group_id || message.text().is_none() {
+            return None;
+        }
+        let reply = message.reply_to_message()?;
+        let review = ReviewEntity::get_by_message(reply.id.0).await.ok()??;
+        (review.status == ReviewStatus::Pending).then_some(review)
+    })
+    .chain(filter_admin_msg())
+    .endpoint(review_note)
+}
+
+async fn review_note(
+    bot: Bot,
+    msg: Message,
+    uploader: ExloliUploader,
+    review: ReviewEntity,
+) -> Result<()> {
+    let note = msg.text().unwrap_or_default();
+    info!("{}: 附言批准 {} {}", msg.from().unwrap().id, review.gallery_id, note);
+    if ReviewEntity::decide(review.gallery_id, ReviewStatus::Approved, Some(note))
+        .await?
+        .rows_affected()
+        == 0
+    {
+        return Ok(());
+    }
+    bot.edit_message_reply_markup(msg.chat.id, MessageId(review.message_id)).await?;
+    reply_to!(bot, msg, "已批准，正在发布").await?;
+    let entity = GalleryEntity::get(review.gallery_id).await?.context("找不到画廊")?;
+    uploader.approve(&entity.url()).await?;
+    Ok(())
+}
@@ -8,7 +8,11 @@ use teloxide::types::{
 use teloxide::utils::html::link;
 
 use crate::bot::utils::CallbackData;
-use crate::database::{ChallengeView, GalleryEntity, MessageEntity, TelegraphEntity};
+use crate::config::Channel;
+use crate::database::{
+    ChallengeView, GalleryEntity, ImageFlag, MessageEntity, RelationKind, ReviewStatus,
+    TelegraphEntity,
+};
 use crate::tags::EhTagTransDB;
 
 pub fn cmd_challenge_keyboard(
@@ -24,19 +28,14 @@ pub fn cmd_challenge_keyboard(
     }))
 }
 
-pub async fn cmd_best_text(
-    start: i32,
-    end: i32,
-    offset: i32,
-    channel: Recipient,
-) -> Result<String> {
+pub async fn cmd_best_text(start: i32, end: i32, offset: i32, channel: &Channel) -> Result<String> {
     let start = Utc::now().date_naive() - Duration::days(start as i64);
     let end = Utc::now().date_naive() - Duration::days(end as i64);
 
     let mut text = format!("最近 {start} ~ {end} 天的本子排名（{offset}）");
 
     for (score, title, gid) in GalleryEntity::list(start, end, 20, offset).await? {
-        let url = gallery_preview_url(channel.clone(), gid).await?;
+        let url = gallery_preview_url(channel, gid).await?;
         text.push_str(&format!("\n<code>{:.2}</code> - {}", score * 100., link(&url, &title),));
     }
 
@@ -84,9 +83,62 @@ pub fn poll_keyboard(poll_id: i64, votes: &[i32; 5]) -> InlineKeyboardMarkup {
     InlineKeyboardMarkup::new(options)
 }
 
-pub async fn gallery_preview_url(channel_id: Recipient, gallery_id: i32) -> Result<String> {
+pub fn flag_keyboard(image_id: u32) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback(
+            "标记为广告",
+            CallbackData::FlagImage(image_id, ImageFlag::Ad).pack(),
+        ),
+        InlineKeyboardButton::callback(
+            "标记为无效",
+            CallbackData::FlagImage(image_id, ImageFlag::Broken).pack(),
+        ),
+    ]])
+}
+
+pub fn duplicate_keyboard(gallery: i32, related: i32) -> InlineKeyboardMarkup {
+    let button = |text: &str, kind| {
+        InlineKeyboardButton::callback(
+            text,
+            CallbackData::DuplicateDecision(gallery, related, kind).pack(),
+        )
+    };
+    InlineKeyboardMarkup::new(vec![vec![
+        button("作为新版本发布", RelationKind::Version),
+        button("单独发布", RelationKind::Distinct),
+        button("跳过", RelationKind::Duplicate),
+    ]])
+}
+
+pub fn review_keyboard(gallery: i32) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback(
+            "批准",
+            CallbackData::Review(gallery, ReviewStatus::Approved).pack(),
+        ),
+        InlineKeyboardButton::callback("附言后批准", CallbackData::ReviewNote(gallery).pack()),
+        InlineKeyboardButton::callback(
+            "拒绝",
+            CallbackData::Review(gallery, ReviewStatus::Rejected).pack(),
+        ),
+    ]])
+}
+
+/// 将数据库中记录的频道 ID 转换为 Recipient，数字为会话 ID，否则为频道用户名
+pub fn channel_recipient(channel_id: &str) -> Recipient {
+    match channel_id.parse::<i64>() {
+        Ok(id) => Recipient::Id(ChatId(id)),
+        Err(_) => Recipient::ChannelUsername(channel_id.to_string()),
+    }
+}
+
+/// 画廊的预览链接，优先使用指定频道中的消息，其次是其他频道中的消息，最后是 telegraph 文章
+pub async fn gallery_preview_url(channel: &Channel, gallery_id: i32) -> Result<String> {
+    if let Some(msg) = MessageEntity::get_by_gallery_in(&channel.key(), gallery_id).await? {
+        return Ok(url_of(channel.channel_id.clone(), msg.id).to_string());
+    }
     if let Some(msg) = MessageEntity::get_by_gallery(gallery_id).await? {
-        return Ok(url_of(channel_id, msg.id).to_string());
+        return Ok(url_of(channel_recipient(&msg.channel_id), msg.id).to_string());
     }
     if let Some(telehraph) = TelegraphEntity::get(gallery_id).await? {
         return Ok(telehraph.url);
@@ -6,6 +6,9 @@ mod scheduler;
 mod utils;
 
 pub use dispatcher::start_dispatcher;
+pub(crate) use handlers::{
+    channel_recipient, duplicate_keyboard, gallery_preview_url, review_keyboard, url_of,
+};
 use teloxide::adaptors::{CacheMe, DefaultParseMode, Throttle};
 
 pub type Bot = CacheMe<DefaultParseMode<Throttle<teloxide::Bot>>>;
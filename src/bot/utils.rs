@@ -4,7 +4,6 @@ use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use dashmap::DashMap;
-use image::EncodableLayout;
 use serde::{Deserialize, Serialize};
 use teloxide::prelude::*;
 use tokio::sync::mpsc::{channel, Receiver};
@@ -12,7 +11,9 @@ use tokio::sync::Mutex;
 use tokio::time::sleep;
 use tracing::{info, warn};
 
-use crate::database::ChallengeView;
+use crate::database::{ChallengeView, ImageEntity, ImageFlag, RelationKind, ReviewStatus};
+use crate::storage::StorageBackend;
+use crate::utils::has_qrcode;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CallbackData {
@@ -24,6 +25,14 @@ pub enum CallbackData {
     PrevPage(i32, i32, i32),
     /// 挑战 ID、画师名称
     Challenge(i64, String),
+    /// 画廊 ID、疑似重复的画廊 ID、管理员的决定
+    DuplicateDecision(i32, i32, RelationKind),
+    /// 图片 ID、图片标记
+    FlagImage(u32, ImageFlag),
+    /// 画廊 ID、管理员的决定
+    Review(i32, ReviewStatus),
+    /// 画廊 ID，附言后批准
+    ReviewNote(i32),
 }
 
 impl CallbackData {
@@ -33,6 +42,10 @@ impl CallbackData {
             Self::NextPage(a, b, c) => format!("> {} {} {}", a, b, c),
             Self::PrevPage(a, b, c) => format!("< {} {} {}", a, b, c),
             Self::Challenge(a, b) => format!("challenge {}:{}", a, b),
+            Self::DuplicateDecision(a, b, c) => format!("dup {} {} {}", a, b, c.as_str()),
+            Self::FlagImage(a, b) => format!("flag {} {}", a, b.as_str()),
+            Self::Review(a, b) => format!("review {} {}", a, b.as_str()),
+            Self::ReviewNote(a) => format!("note {}", a),
         }
     }
 
@@ -57,6 +70,24 @@ impl CallbackData {
                 let (a, b) = data.split_once(':')?;
                 Some(Self::Challenge(a.parse().ok()?, b.to_string()))
             }
+            "dup" => {
+                let (a, data) = data.split_once(' ')?;
+                let (b, c) = data.split_once(' ')?;
+                Some(Self::DuplicateDecision(
+                    a.parse().ok()?,
+                    b.parse().ok()?,
+                    RelationKind::parse(c)?,
+                ))
+            }
+            "flag" => {
+                let (a, b) = data.split_once(' ')?;
+                Some(Self::FlagImage(a.parse().ok()?, b.parse().ok()?))
+            }
+            "review" => {
+                let (a, b) = data.split_once(' ')?;
+                Some(Self::Review(a.parse().ok()?, ReviewStatus::parse(b)?))
+            }
+            "note" => Some(Self::ReviewNote(data.parse().ok()?)),
             _ => None,
         }
     }
@@ -126,11 +157,11 @@ impl ChallengeLocker {
 pub struct ChallengeProvider(Arc<Mutex<Receiver<Vec<ChallengeView>>>>);
 
 impl ChallengeProvider {
-    pub fn new() -> Self {
+    pub fn new(storage: StorageBackend) -> Self {
         let (tx, rx) = channel(5);
         tokio::spawn(async move {
             loop {
-                match Self::_get_challenge().await {
+                match Self::_get_challenge(&storage).await {
                     Ok(challenge) => {
                         tx.send(challenge).await.unwrap();
                     }
@@ -143,7 +174,7 @@ impl ChallengeProvider {
         Self(Arc::new(Mutex::new(rx)))
     }
 
-    async fn _get_challenge() -> Result<Vec<ChallengeView>> {
+    async fn _get_challenge(storage: &StorageBackend) -> Result<Vec<ChallengeView>> {
         loop {
             let challenge = ChallengeView::get_random().await?;
             if challenge.is_empty() {
@@ -151,15 +182,11 @@ impl ChallengeProvider {
                 continue;
             }
             let answer = &challenge[0];
-            let url = if answer.url.starts_with("https://") {
-                answer.url.clone()
-            } else {
-                format!("https://telegra.ph{}", answer.url)
-            };
-            let resp = reqwest::get(&url).await?;
+            let resp = reqwest::get(answer.url(storage)).await?;
             let data = resp.bytes().await?;
             if has_qrcode(&data)? {
                 info!("跳过包含二维码的图片");
+                ImageEntity::update_flag(answer.image_id as u32, ImageFlag::Ad).await?;
                 continue;
             }
             return Ok(challenge);
@@ -170,10 +197,3 @@ impl ChallengeProvider {
         self.0.lock().await.recv().await
     }
 }
-
-pub fn has_qrcode(data: &[u8]) -> Result<bool> {
-    let image = image::load_from_memory(data)?.into_luma8();
-    let mut decoder = quircs::Quirc::default();
-    let codes = decoder.identify(image.width() as usize, image.height() as usize, image.as_bytes());
-    Ok(codes.count() > 0)
-}
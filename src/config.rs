@@ -1,12 +1,12 @@
+use std::fmt::Display;
+use std::path::PathBuf;
 use std::time::Duration;
 
-use anyhow::Result;
-use duration_str::deserialize_duration;
-use once_cell::sync::OnceCell;
+use anyhow::{anyhow, Result};
+use chrono::NaiveTime;
+use duration_str::{deserialize_duration, deserialize_option_duration};
 use serde::Deserialize;
-use teloxide::types::{ChatId, Recipient};
-
-pub static CHANNEL_ID: OnceCell<String> = OnceCell::new();
+use teloxide::types::{Chat, ChatId, Recipient};
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
@@ -19,10 +19,35 @@ pub struct Config {
     pub interval: Duration,
     /// Sqlite 数据库位置
     pub database_url: String,
+    /// 感知哈希的汉明距离不超过该值、并且逐像素比较后确认相同的图片会复用已经上传的文件
+    /// 不设置则只复用内容完全一致的图片，受索引限制不能超过 3
+    pub phash_threshold: Option<u32>,
+    /// 出现在至少该数量的画廊中的图片会被自动标记为广告，同一画廊的不同版本只计一次
+    /// 不设置则不自动标记
+    pub ad_gallery_count: Option<i32>,
+    /// H@H 下载目录，重新上传损坏的图片时优先从中读取，不设置则只从 E 站下载
+    pub archive_dir: Option<PathBuf>,
     pub exhentai: ExHentai,
     pub telegraph: Telegraph,
+    /// 预览页面的发布方式，不设置则发布到 telegraph
+    #[serde(default)]
+    pub preview: Preview,
     pub telegram: Telegram,
-    pub s3: S3,
+    /// 图片等文件的存储方式，不设置则上传至 s3
+    #[serde(default)]
+    pub storage: Storage,
+    /// s3 配置，使用 s3 存储时必须设置，改用其他存储后保留时用于生成之前上传的文件的地址
+    pub s3: Option<S3>,
+    /// 重复画廊检测，不设置则不检测
+    pub duplicate: Option<Duplicate>,
+    /// 上传规则，不设置则上传所有搜索到的画廊
+    #[serde(default)]
+    pub filter: Filter,
+    /// 发布队列，不设置则扫描到新画廊后立即发布
+    pub queue: Option<Queue>,
+    /// 已发布画廊的元数据更新
+    #[serde(default)]
+    pub update: Update,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -39,26 +64,146 @@ pub struct ExHentai {
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Telegraph {
-    /// Telegraph token
+    /// Telegraph token，会作为账号池中的第一个账号
     pub access_token: String,
+    /// 账号池中保持可用的账号数量，不足时会自动创建新账号，默认为 1
+    pub pool_size: Option<usize>,
+    /// 连续发布失败达到该次数后停用账号，默认为 3
+    pub max_failures: Option<i32>,
     /// 文章作者名称
     pub author_name: String,
     /// 文章作者连接
     pub author_url: String,
+    /// 文章开头展示的画廊信息，按填写的顺序展示，不设置则展示全部
+    #[serde(default = "ArticleHeader::all")]
+    pub header: Vec<ArticleHeader>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArticleHeader {
+    /// 日文标题与英文标题
+    Title,
+    /// 翻译后的标签
+    Tags,
+    /// 上传者
+    Uploader,
+    /// 发布时间
+    Posted,
+    /// 频道消息与原始画廊的链接
+    Links,
+    /// 均匀抽取若干页拼成的缩略图
+    Grid,
+}
+
+impl ArticleHeader {
+    fn all() -> Vec<Self> {
+        vec![Self::Title, Self::Tags, Self::Uploader, Self::Posted, Self::Links, Self::Grid]
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum Preview {
+    /// 发布到 telegraph
+    #[default]
+    Telegraph,
+    /// 渲染为静态 HTML 页面
+    Html(HtmlPreview),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HtmlPreview {
+    /// 输出目录，不设置则上传至存储的 preview 目录下
+    pub dir: Option<PathBuf>,
+    /// 输出目录的公开访问地址
+    pub host: String,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Telegram {
-    /// 频道 id
-    pub channel_id: Recipient,
     /// bot 名称
     pub bot_id: String,
     /// bot token
     pub token: String,
-    /// 讨论组 ID
-    pub group_id: ChatId,
     /// 入口讨论组 ID
     pub auth_group_id: ChatId,
+    /// 主频道，使用 [filter] 中的上传规则，管理员操作和审核都在主频道的讨论组中进行
+    #[serde(flatten)]
+    pub main: Channel,
+    /// 额外的频道，各自使用独立的上传规则
+    #[serde(default)]
+    pub channels: Vec<RoutedChannel>,
+}
+
+impl Telegram {
+    /// 所有频道，第一个为主频道
+    pub fn all_channels(&self) -> impl Iterator<Item = &Channel> {
+        std::iter::once(&self.main).chain(self.channels.iter().map(|c| &c.channel))
+    }
+
+    /// 是否为某个频道的讨论组
+    pub fn is_group(&self, chat_id: ChatId) -> bool {
+        self.all_channels().any(|c| c.group_id == chat_id)
+    }
+
+    /// 根据讨论组查找频道，找不到时返回主频道
+    pub fn channel_of_group(&self, chat_id: ChatId) -> &Channel {
+        self.all_channels().find(|c| c.group_id == chat_id).unwrap_or(&self.main)
+    }
+
+    /// 根据会话查找频道
+    pub fn channel_of_chat(&self, chat: &Chat) -> Option<&Channel> {
+        self.all_channels().find(|c| c.is_chat(chat))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Channel {
+    /// 频道名称，可以在上传规则的 route 中使用，不设置则只能使用频道 ID
+    pub name: Option<String>,
+    /// 频道 id
+    pub channel_id: Recipient,
+    /// 讨论组 ID
+    pub group_id: ChatId,
+    /// 频道消息的模板，使用 jinja2 语法，不设置则使用默认模板
+    pub template: Option<String>,
+    /// 是否以图片消息发布画廊，封面作为图片，正文作为说明，默认为否
+    pub photo: Option<bool>,
+    /// 消息过长时优先删除的 namespace，按顺序删除
+    #[serde(default)]
+    pub low_priority: Vec<String>,
+    /// 画廊的标题、标签或页数发生变化时，是否在讨论组中回复更新内容，默认为否
+    pub changelog: Option<bool>,
+}
+
+impl Channel {
+    /// 数据库中记录该频道的消息时使用的 ID
+    pub fn key(&self) -> String {
+        self.channel_id.to_string()
+    }
+
+    /// 是否可以用指定的名称指代该频道，名称可以是频道名称或者频道 ID
+    pub fn is_named(&self, name: &str) -> bool {
+        self.name.as_deref() == Some(name) || self.key() == name
+    }
+
+    /// 是否为指定的会话，频道 ID 为用户名时按用户名比较
+    pub fn is_chat(&self, chat: &Chat) -> bool {
+        match &self.channel_id {
+            Recipient::Id(id) => *id == chat.id,
+            Recipient::ChannelUsername(name) => chat.username() == Some(&name[1..]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RoutedChannel {
+    #[serde(flatten)]
+    pub channel: Channel,
+    /// 该频道的上传规则，其中只有 upload 会发布到该频道，不设置则发布所有画廊
+    #[serde(default)]
+    pub filter: Filter,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -73,13 +218,309 @@ pub struct S3 {
     pub access_key: String,
     /// secret-key
     pub secret_key: String,
-    /// 公开访问连接
+    #[serde(flatten)]
+    pub hosts: PublicHosts,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum Storage {
+    /// 上传至 s3，使用 [s3] 中的配置
+    #[default]
+    S3,
+    /// 保存到本地目录，由其他静态文件服务器提供访问
+    Local(LocalStorage),
+    /// 上传至 WebDAV
+    Webdav(WebdavStorage),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LocalStorage {
+    /// 保存目录
+    pub dir: PathBuf,
+    #[serde(flatten)]
+    pub hosts: PublicHosts,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebdavStorage {
+    /// WebDAV 地址，文件会保存在该目录下
+    pub url: String,
+    /// 用户名，不设置则不进行认证
+    pub username: Option<String>,
+    /// 密码
+    pub password: Option<String>,
+    #[serde(flatten)]
+    pub hosts: PublicHosts,
+}
+
+/// 存储中文件的公开访问地址
+#[derive(Debug, Clone, Deserialize)]
+pub struct PublicHosts {
+    /// 公开访问连接，可以带上路径，不写协议时默认为 https
     pub host: String,
+    /// 公开访问连接的镜像，下载图片时主地址失败会依次尝试
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+}
+
+impl PublicHosts {
+    /// 生成文件的公开访问地址，主地址在前，镜像地址在后
+    pub fn urls(&self, key: &str) -> Vec<String> {
+        std::iter::once(&self.host)
+            .chain(&self.mirrors)
+            .map(|host| match host.contains("://") {
+                true => format!("{}/{}", host.trim_end_matches('/'), key),
+                false => format!("https://{}/{}", host.trim_end_matches('/'), key),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Duplicate {
+    /// 页面重合度超过该值时视为重复，为 0~1 的小数
+    pub threshold: f32,
+    /// 感知哈希的汉明距离不超过该值的页面视为同一页，受索引限制不能超过 3
+    pub distance: u32,
+    /// 发现重复时的处理方式
+    pub action: DuplicateAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateAction {
+    /// 直接跳过
+    Skip,
+    /// 作为另一个版本，回复在原画廊的消息下
+    Reply,
+    /// 询问管理员
+    Ask,
 }
 
 impl Config {
     pub fn new(path: &str) -> Result<Self> {
         let s = std::fs::read_to_string(path)?;
-        Ok(toml::from_str(&s)?)
+        let config: Self = toml::from_str(&s)?;
+        // 感知哈希按 16 位分段建立索引，距离超过 3 时无法保证能查到所有结果
+        if config.phash_threshold.is_some_and(|d| d > 3) {
+            return Err(anyhow!("phash_threshold 不能超过 3"));
+        }
+        if config.duplicate.as_ref().is_some_and(|d| d.distance > 3) {
+            return Err(anyhow!("duplicate.distance 不能超过 3"));
+        }
+        // route 指向不存在的频道时，画廊既不会发布也不会被记录
+        let filter = &config.filter;
+        for action in filter.rules.iter().map(|r| &r.action).chain([&filter.default]) {
+            if let RuleAction::Route(name) = action {
+                if !config.telegram.all_channels().any(|c| c.is_named(name)) {
+                    return Err(anyhow!("上传规则中的频道 {} 不存在", name));
+                }
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Queue {
+    /// 两条频道消息之间的最小间隔
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub min_gap: Duration,
+    /// 允许发布的时间段，不设置则全天都可以发布
+    #[serde(default)]
+    pub slots: Vec<TimeRange>,
+    /// 不发布的时间段，优先于 slots
+    #[serde(default)]
+    pub quiet: Vec<TimeRange>,
+    /// 每天最多从队列中发布的数量，不设置则不限制
+    pub daily_cap: Option<i32>,
+}
+
+impl Queue {
+    /// 指定的时刻是否允许发布
+    pub fn is_open(&self, time: NaiveTime) -> bool {
+        (self.slots.is_empty() || self.slots.iter().any(|r| r.contains(time)))
+            && !self.quiet.iter().any(|r| r.contains(time))
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Update {
+    /// 每轮最多检查的画廊数量，默认为 20
+    pub batch: Option<i32>,
+    /// 两次检查之间的最长间隔，默认为 60 天
+    #[serde(default, deserialize_with = "deserialize_option_duration")]
+    pub max_interval: Option<Duration>,
+}
+
+/// 一天中的时间段，格式为 HH:MM-HH:MM，结束时间早于开始时间时表示跨越零点
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TimeRange {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeRange {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl TryFrom<String> for TimeRange {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        let (start, end) = s.split_once('-').ok_or_else(|| anyhow!("无效的时间段：{}", s))?;
+        Ok(Self {
+            start: NaiveTime::parse_from_str(start.trim(), "%H:%M")?,
+            end: NaiveTime::parse_from_str(end.trim(), "%H:%M")?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Filter {
+    /// 没有规则命中时的处理方式，默认为上传
+    #[serde(default)]
+    pub default: RuleAction,
+    /// 按顺序匹配的规则，第一条命中的规则决定处理方式
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    /// 等待审核的画廊超过该时间未处理则视为过期，不再发布，不设置则不会过期
+    #[serde(default, deserialize_with = "deserialize_option_duration")]
+    pub expire: Option<Duration>,
+}
+
+/// 上传规则，所有设置了的条件都满足时命中
+#[derive(Debug, Clone, Deserialize)]
+pub struct Rule {
+    /// 规则名称，用于日志和 /explain
+    pub name: String,
+    /// 命中后的处理方式
+    pub action: RuleAction,
+    /// 包含其中任意一个标签，格式为 namespace:tag，namespace 或 tag 可以为 *，省略 namespace 时匹配任意 namespace
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// 不包含其中任何一个标签，格式同上
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// 页数范围
+    pub pages: Option<Range<usize>>,
+    /// 收藏数范围
+    pub favorites: Option<Range<i32>>,
+    /// 评分范围，0~5，尚未评分的画廊不满足该条件
+    pub rating: Option<Range<f32>>,
+    /// 分类为其中之一，如 Doujinshi、Manga，不区分大小写
+    #[serde(default)]
+    pub category: Vec<String>,
+    /// 语言为其中之一，不区分大小写，没有语言标签的画廊视为 japanese
+    #[serde(default)]
+    pub language: Vec<String>,
+    /// 上传者为其中之一
+    #[serde(default)]
+    pub uploader: Vec<String>,
+    /// 发布至今的小时数范围
+    pub age: Option<Range<i64>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    /// 上传并发布
+    #[default]
+    Upload,
+    /// 跳过
+    Skip,
+    /// 暂不发布，等待管理员处理
+    Hold,
+    /// 发布到指定频道
+    Route(String),
+}
+
+/// 闭区间，不设置的一侧不限制
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Range<T> {
+    pub min: Option<T>,
+    pub max: Option<T>,
+}
+
+impl Display for RuleAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Upload => write!(f, "上传"),
+            Self::Skip => write!(f, "跳过"),
+            Self::Hold => write!(f, "等待审核"),
+            Self::Route(channel) => write!(f, "发布到 {}", channel),
+        }
+    }
+}
+
+impl<T: Display> Display for Range<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(min) = &self.min {
+            write!(f, "{} ", min)?;
+        }
+        write!(f, "~")?;
+        if let Some(max) = &self.max {
+            write!(f, " {}", max)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: PartialOrd> Range<T> {
+    pub fn contains(&self, value: T) -> bool {
+        self.min.as_ref().is_none_or(|min| &value >= min)
+            && self.max.as_ref().is_none_or(|max| &value <= max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queue_time_ranges() {
+        let time = |s| NaiveTime::parse_from_str(s, "%H:%M").unwrap();
+        let range = |s: &str| TimeRange::try_from(s.to_owned()).unwrap();
+        let queue = Queue {
+            min_gap: Duration::from_secs(60),
+            slots: vec![range("08:00-12:00"), range("18:00-00:00")],
+            quiet: vec![range("23:00-01:00")],
+            daily_cap: None,
+        };
+        assert!(queue.is_open(time("08:00")));
+        assert!(!queue.is_open(time("12:00")));
+        assert!(queue.is_open(time("22:59")));
+        assert!(!queue.is_open(time("23:30")));
+        assert!(!queue.is_open(time("00:30")));
+        assert!(TimeRange::try_from("8:00".to_owned()).is_err());
+    }
+
+    #[test]
+    fn queue_open_without_slots() {
+        let time = |s| NaiveTime::parse_from_str(s, "%H:%M").unwrap();
+        let range = |s: &str| TimeRange::try_from(s.to_owned()).unwrap();
+        // 不设置 slots 时全天都可以发布，只排除 quiet
+        let mut queue = Queue {
+            min_gap: Duration::from_secs(60),
+            slots: vec![],
+            quiet: vec![range("22:00-07:30")],
+            daily_cap: None,
+        };
+        assert!(queue.is_open(time("07:30")));
+        assert!(queue.is_open(time("21:59")));
+        assert!(!queue.is_open(time("22:00")));
+        assert!(!queue.is_open(time("03:00")));
+        queue.quiet.clear();
+        assert!(queue.is_open(time("03:00")));
+        // 开始时间与结束时间相同的时间段为空
+        assert!(!range("10:00-10:00").contains(time("10:00")));
     }
 }
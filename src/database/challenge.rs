@@ -4,6 +4,7 @@ use sqlx::sqlite::SqliteQueryResult;
 use sqlx::Result;
 
 use super::db::DB;
+use crate::storage::StorageBackend;
 
 #[derive(FromRow, Clone)]
 pub struct ChallengeView {
@@ -12,7 +13,8 @@ pub struct ChallengeView {
     pub page: i32,
     pub artist: String,
     pub image_id: i32,
-    pub url: String,
+    pub storage: String,
+    pub key: String,
     pub score: f32,
 }
 
@@ -27,6 +29,11 @@ pub struct ChallengeHistory {
 }
 
 impl ChallengeView {
+    /// 图片的公开访问地址
+    pub fn url(&self, storage: &StorageBackend) -> String {
+        storage.image_urls(&self.storage, &self.key).swap_remove(0)
+    }
+
     pub async fn get_random() -> Result<Vec<Self>> {
         sqlx::query_as!(
             Self,
@@ -35,20 +42,19 @@ impl ChallengeView {
                 id as "id: i32",
                 token,
                 page as "page: i32",
-                artist as "artist!",
+                artist as "artist!: String",
                 image_id as "image_id: i32",
-                url,
+                storage as "storage!: String",
+                key as "key!: String",
                 score as "score: f32"
             FROM (
                 -- 此处使用 group by 嵌套 random，因为默认情况下 group by 只会显示每组的第一个结果
                 SELECT * FROM (
                     SELECT * FROM challenge_view
-                    WHERE score > 0.8 AND image_id NOT IN (
-                        -- 此处过滤掉出现在大于 5 个画廊中的图片，因为大概率是广告
-                        -- 还有第一页和最后一页
-                        -- 这个查询太耗时了，现在有基于二维码的过滤了，暂时禁用看一下效果
-                        -- SELECT image_id FROM page GROUP BY image_id HAVING COUNT(gallery_id) > 5
-                        -- UNION
+                    -- 尚未从 telegraph 迁移的图片无法访问，没有对象键的旧图片也无法生成地址
+                    WHERE score > 0.8 AND storage != 'telegraph' AND key != '' AND image_id NOT IN (
+                        -- 此处过滤掉第一页和最后一页
+                        -- 被标记为广告或无效的图片已经在 challenge_view 中过滤掉了
                         SELECT image_id FROM page GROUP BY gallery_id HAVING page = MAX(page)
                         UNION
                         SELECT image_id FROM page GROUP BY gallery_id HAVING page = 1
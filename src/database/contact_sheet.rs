@@ -0,0 +1,100 @@
+use chrono::{NaiveDateTime, Utc};
+use sqlx::sqlite::SqliteQueryResult;
+use sqlx::Result;
+use tracing::Level;
+
+use super::db::DB;
+use crate::storage::StorageBackend;
+
+/// 画廊的缩略图，由均匀抽取的若干页拼接而成
+#[derive(sqlx::FromRow, Debug)]
+pub struct ContactSheetEntity {
+    /// 画廊 ID
+    pub gallery_id: i32,
+    /// 存储后端的 ID
+    pub storage: String,
+    /// 对象键，旧缩略图的地址无法与配置中的地址对应时为空，此时继续使用 url
+    pub key: String,
+    /// 旧缩略图的完整地址，新缩略图为空
+    url: String,
+    /// 生成时间
+    pub created_at: NaiveDateTime,
+}
+
+impl ContactSheetEntity {
+    /// 创建一条记录，如果已存在则覆盖
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn create(gallery_id: i32, storage: &str, key: &str) -> Result<SqliteQueryResult> {
+        let now = Utc::now().naive_utc();
+        sqlx::query!(
+            "REPLACE INTO contact_sheet (gallery_id, storage, key, url, created_at) VALUES (?, ?, ?, '', ?)",
+            gallery_id,
+            storage,
+            key,
+            now
+        )
+        .execute(&*DB)
+        .await
+    }
+
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn get(gallery_id: i32) -> Result<Option<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"SELECT gallery_id as "gallery_id: i32", storage, key, url, created_at FROM contact_sheet WHERE gallery_id = ?"#,
+            gallery_id
+        )
+        .fetch_optional(&*DB)
+        .await
+    }
+
+    /// 列出所有还没有对象键的旧缩略图
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn list_without_key() -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"SELECT gallery_id as "gallery_id: i32", storage, key, url, created_at FROM contact_sheet WHERE key = ''"#
+        )
+        .fetch_all(&*DB)
+        .await
+    }
+
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn update_key(
+        gallery_id: i32,
+        storage: &str,
+        key: &str,
+    ) -> Result<SqliteQueryResult> {
+        sqlx::query!(
+            "UPDATE contact_sheet SET storage = ?, key = ? WHERE gallery_id = ?",
+            storage,
+            key,
+            gallery_id
+        )
+        .execute(&*DB)
+        .await
+    }
+
+    /// 列出所有已发布但还没有缩略图的画廊
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn list_missing() -> Result<Vec<i32>> {
+        sqlx::query_scalar!(
+            r#"SELECT DISTINCT gallery.id as "id: i32"
+            FROM gallery
+            JOIN message ON message.gallery_id = gallery.id
+            LEFT JOIN contact_sheet ON contact_sheet.gallery_id = gallery.id
+            WHERE gallery.deleted = FALSE AND contact_sheet.gallery_id IS NULL
+            ORDER BY gallery.id DESC"#
+        )
+        .fetch_all(&*DB)
+        .await
+    }
+
+    /// 缩略图的公开访问地址
+    pub fn url(&self, storage: &StorageBackend) -> String {
+        match self.key.is_empty() {
+            true => self.url.clone(),
+            false => storage.image_urls(&self.storage, &self.key).swap_remove(0),
+        }
+    }
+}
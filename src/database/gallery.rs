@@ -11,7 +11,6 @@ use sqlx::{Database, Result, Sqlite};
 use tracing::Level;
 
 use super::db::DB;
-use crate::config::CHANNEL_ID;
 use crate::ehentai::EhGallery;
 
 // 此处使用 IndexMap，因为我们需要保证相同的 tag 每次序列化的结果都是一样的
@@ -41,6 +40,8 @@ pub struct GalleryEntity {
     pub deleted: bool,
     /// 发布时间
     pub posted: Option<NaiveDateTime>,
+    /// 上传者，旧画廊可能为空
+    pub uploader: Option<String>,
 }
 
 impl GalleryEntity {
@@ -53,7 +54,7 @@ impl GalleryEntity {
         let pages = g.pages.len() as i32;
         let parent = g.parent.as_ref().map(|g| g.id());
         sqlx::query!(
-            "REPLACE INTO gallery (id, token, title, title_jp, tags, favorite, pages, parent, deleted, posted) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            "REPLACE INTO gallery (id, token, title, title_jp, tags, favorite, pages, parent, deleted, posted, uploader) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
             id,
             token,
             g.title,
@@ -64,6 +65,7 @@ impl GalleryEntity {
             parent,
             false,
             g.posted,
+            g.uploader,
         )
             .execute(&*DB)
             .await
@@ -80,12 +82,12 @@ impl GalleryEntity {
             .await
     }
 
-    /// 根据消息 ID 获取一条记录
-    pub async fn get_by_msg(id: i32) -> Result<Option<GalleryEntity>> {
+    /// 根据频道和消息 ID 获取一条记录
+    pub async fn get_by_msg(channel_id: &str, id: i32) -> Result<Option<GalleryEntity>> {
         sqlx::query_as(
             "SELECT gallery.* FROM gallery JOIN message ON gallery.id = message.gallery_id AND message.channel_id = ? WHERE message.id = ? AND gallery.deleted = FALSE"
         )
-            .bind(CHANNEL_ID.get().unwrap())
+            .bind(channel_id)
             .bind(id)
             .fetch_optional(&*DB)
             .await
@@ -147,6 +149,20 @@ impl GalleryEntity {
         Ok(record.into_iter().map(|x| (x.score as f32, x.title, x.id as i32)).collect())
     }
 
+    /// 按 ID 顺序列出 ID 大于 after 的已发布画廊，用于分批处理所有画廊
+    pub async fn list_published_after(after: i32, limit: i32) -> Result<Vec<Self>> {
+        sqlx::query_as(
+            r#"SELECT * FROM gallery
+            WHERE id > ? AND deleted = FALSE
+                AND EXISTS (SELECT 1 FROM message WHERE message.gallery_id = gallery.id)
+            ORDER BY id LIMIT ?"#,
+        )
+        .bind(after)
+        .bind(limit)
+        .fetch_all(&*DB)
+        .await
+    }
+
     /// 列出所有 80 分以上或最近两个月上传的画廊
     pub async fn list_scans() -> Result<Vec<Self>> {
         let since = Utc::now().date_naive() - Duration::days(60);
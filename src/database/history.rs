@@ -0,0 +1,168 @@
+use std::fmt::Display;
+
+use chrono::NaiveDateTime;
+use indexmap::IndexMap;
+use sqlx::sqlite::SqliteQueryResult;
+use sqlx::Result;
+use tracing::Level;
+
+use super::db::DB;
+use super::{GalleryEntity, TagsEntity};
+use crate::ehentai::EhGallery;
+
+/// 画廊的一次元数据变化，只记录发生变化的部分
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct GalleryHistoryEntity {
+    pub id: i64,
+    /// 画廊 ID
+    pub gallery_id: i32,
+    /// 旧标题，标题没有变化时为空
+    pub old_title: Option<String>,
+    /// 新标题，标题没有变化时为空
+    pub new_title: Option<String>,
+    /// 新增的标签
+    pub added_tags: TagsEntity,
+    /// 移除的标签
+    pub removed_tags: TagsEntity,
+    /// 旧页数，页数没有变化时为空
+    pub old_pages: Option<i32>,
+    /// 新页数，页数没有变化时为空
+    pub new_pages: Option<i32>,
+    /// 发现变化的时间
+    pub created_at: NaiveDateTime,
+}
+
+impl GalleryHistoryEntity {
+    /// 比较数据库中的记录与 E 站上的最新信息，没有变化时返回 None
+    pub fn diff(old: &GalleryEntity, new: &EhGallery, now: NaiveDateTime) -> Option<Self> {
+        let (old_title, new_title) = match old.title == new.title {
+            true => (None, None),
+            false => (Some(old.title.clone()), Some(new.title.clone())),
+        };
+        let pages = new.pages.len() as i32;
+        let (old_pages, new_pages) = match old.pages == pages {
+            true => (None, None),
+            false => (Some(old.pages), Some(pages)),
+        };
+        let history = Self {
+            id: 0,
+            gallery_id: old.id,
+            old_title,
+            new_title,
+            added_tags: TagsEntity(tag_diff(&new.tags, &old.tags)),
+            removed_tags: TagsEntity(tag_diff(&old.tags, &new.tags)),
+            old_pages,
+            new_pages,
+            created_at: now,
+        };
+        (history.new_title.is_some()
+            || history.new_pages.is_some()
+            || !history.added_tags.is_empty()
+            || !history.removed_tags.is_empty())
+        .then_some(history)
+    }
+
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn create(&self) -> Result<SqliteQueryResult> {
+        let added = serde_json::to_string(&self.added_tags.0).unwrap();
+        let removed = serde_json::to_string(&self.removed_tags.0).unwrap();
+        sqlx::query!(
+            "INSERT INTO gallery_history
+            (gallery_id, old_title, new_title, added_tags, removed_tags, old_pages, new_pages, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            self.gallery_id,
+            self.old_title,
+            self.new_title,
+            added,
+            removed,
+            self.old_pages,
+            self.new_pages,
+            self.created_at,
+        )
+        .execute(&*DB)
+        .await
+    }
+
+    /// 按时间顺序列出画廊的所有变化
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn list(gallery_id: i32) -> Result<Vec<Self>> {
+        sqlx::query_as("SELECT * FROM gallery_history WHERE gallery_id = ? ORDER BY created_at")
+            .bind(gallery_id)
+            .fetch_all(&*DB)
+            .await
+    }
+}
+
+/// 以纯文本的形式展示变化，每项一行，格式为 +namespace:tag 或 -namespace:tag
+impl Display for GalleryHistoryEntity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut lines = vec![];
+        if let (Some(old), Some(new)) = (&self.old_title, &self.new_title) {
+            lines.push(format!("标题：{} → {}", old, new));
+        }
+        let mut tags = vec![];
+        for (sign, group) in [("+", &self.added_tags), ("-", &self.removed_tags)] {
+            for (ns, list) in group.iter() {
+                tags.extend(list.iter().map(|tag| format!("{}{}:{}", sign, ns, tag)));
+            }
+        }
+        if !tags.is_empty() {
+            lines.push(format!("标签：{}", tags.join(", ")));
+        }
+        if let (Some(old), Some(new)) = (self.old_pages, self.new_pages) {
+            lines.push(format!("页数：{} → {}", old, new));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// 在 a 中但不在 b 中的标签
+fn tag_diff(
+    a: &IndexMap<String, Vec<String>>,
+    b: &IndexMap<String, Vec<String>>,
+) -> IndexMap<String, Vec<String>> {
+    a.iter()
+        .filter_map(|(ns, tags)| {
+            let other = b.get(ns);
+            let diff = tags
+                .iter()
+                .filter(|tag| !other.is_some_and(|o| o.contains(tag)))
+                .cloned()
+                .collect::<Vec<_>>();
+            (!diff.is_empty()).then(|| (ns.clone(), diff))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(items: &[(&str, &[&str])]) -> IndexMap<String, Vec<String>> {
+        items
+            .iter()
+            .map(|(ns, tags)| (ns.to_string(), tags.iter().map(|t| t.to_string()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn changelog() {
+        let old = tags(&[("female", &["lolicon", "twintails"]), ("language", &["chinese"])]);
+        let new = tags(&[("female", &["lolicon"]), ("other", &["full color"])]);
+        let history = GalleryHistoryEntity {
+            id: 0,
+            gallery_id: 1,
+            old_title: Some("a".to_owned()),
+            new_title: Some("b".to_owned()),
+            added_tags: TagsEntity(tag_diff(&new, &old)),
+            removed_tags: TagsEntity(tag_diff(&old, &new)),
+            old_pages: None,
+            new_pages: None,
+            created_at: NaiveDateTime::default(),
+        };
+        assert_eq!(
+            history.to_string(),
+            "标题：a → b\n标签：+other:full color, -female:twintails, -language:chinese"
+        );
+    }
+}
@@ -1,8 +1,30 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
 use sqlx::sqlite::SqliteQueryResult;
 use sqlx::Result;
 use tracing::Level;
 
 use super::db::DB;
+use crate::storage::StorageBackend;
+use crate::utils::imagehash::{bands, hamming};
+
+/// 早期上传到 telegraph 的图片所在的存储后端
+pub const STORAGE_TELEGRAPH: &str = "telegraph";
+
+/// 图片标记，被标记的图片不会出现在文章和挑战中
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(i32)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFlag {
+    /// 正常图片
+    Ok = 0,
+    /// 无效图片
+    Broken = 1,
+    /// 广告图片，不会被上传
+    Ad = 2,
+}
 
 #[derive(sqlx::FromRow, Debug)]
 pub struct PageEntity {
@@ -20,17 +42,52 @@ pub struct ImageEntity {
     pub id: u32,
     /// 图片的 sha1sum 前 10 位
     pub hash: String,
-    /// 相对 https://telegra.ph 的图片 URL
+    /// 图片文件完整的 sha1sum，旧图片可能为空
+    ///
+    /// 复用了相似图片的文件时，记录的是该文件的 sha1sum
+    pub sha1: Option<String>,
+    /// 图片的感知哈希（dHash），旧图片可能为空
+    pub phash: Option<i64>,
+    /// 图片标记
+    pub flag: ImageFlag,
+    /// 图片所在的存储后端，早期上传到 telegraph 的图片为 telegraph，需要通过 /migrate_images 迁移
+    pub storage: String,
+    /// 图片在存储后端中的对象键，公开访问地址在使用时根据配置生成
+    ///
+    /// 旧图片的地址无法与配置中的地址对应时为空，此时继续使用 url
+    pub key: String,
+    /// 旧图片的完整地址，新图片为空
     url: String,
+    /// 图片文件的大小，未知时为空
+    pub size: Option<i64>,
+    /// 存储返回的 ETag，未知时为空
+    pub etag: Option<String>,
 }
 
 impl ImageEntity {
     /// 创建一条记录
     #[tracing::instrument(level = Level::DEBUG)]
-    pub async fn create(id: u32, hash: &str, url: &str) -> Result<SqliteQueryResult> {
-        sqlx::query!("INSERT OR IGNORE INTO image (id, hash, url) VALUES (?, ?, ?)", id, hash, url)
-            .execute(&*DB)
-            .await
+    pub async fn create(
+        id: u32,
+        hash: &str,
+        sha1: Option<&str>,
+        phash: Option<i64>,
+        flag: ImageFlag,
+        storage: &str,
+        key: &str,
+    ) -> Result<SqliteQueryResult> {
+        sqlx::query!(
+            "INSERT OR IGNORE INTO image (id, hash, sha1, phash, flag, storage, key, url) VALUES (?, ?, ?, ?, ?, ?, ?, '')",
+            id,
+            hash,
+            sha1,
+            phash,
+            flag,
+            storage,
+            key
+        )
+        .execute(&*DB)
+        .await
     }
 
     /// 根据图片 hash 获取一张图片
@@ -38,13 +95,25 @@ impl ImageEntity {
     pub async fn get_by_hash(hash: &str) -> Result<Option<Self>> {
         sqlx::query_as!(
             Self,
-            r#"SELECT id as "id: u32", hash, url FROM image WHERE hash = ?"#,
+            r#"SELECT id as "id: u32", hash, sha1, phash, flag as "flag: ImageFlag", storage, key, url, size, etag FROM image WHERE hash = ?"#,
             hash
         )
         .fetch_optional(&*DB)
         .await
     }
 
+    /// 根据图片完整的 sha1 获取一张图片
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn get_by_sha1(sha1: &str) -> Result<Option<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"SELECT id as "id: u32", hash, sha1, phash, flag as "flag: ImageFlag", storage, key, url, size, etag FROM image WHERE sha1 = ?"#,
+            sha1
+        )
+        .fetch_optional(&*DB)
+        .await
+    }
+
     /// 获取指定画廊的所有图片，并且按页码排列
     #[tracing::instrument(level = Level::DEBUG)]
     pub async fn get_by_gallery_id(gallery_id: i32) -> Result<Vec<Self>> {
@@ -54,7 +123,14 @@ impl ImageEntity {
             SELECT
                 image.id as "id: u32",
                 image.hash as hash,
-                image.url as url
+                image.sha1 as sha1,
+                image.phash as phash,
+                image.flag as "flag: ImageFlag",
+                image.storage as storage,
+                image.key as key,
+                image.url as url,
+                image.size as size,
+                image.etag as etag
             FROM image
             JOIN page ON page.image_id = image.id
             WHERE page.gallery_id = ?
@@ -66,11 +142,134 @@ impl ImageEntity {
         .await
     }
 
-    pub fn url(&self) -> String {
-        if self.url.starts_with("/file/") {
-            format!("https://telegra.ph{}", self.url)
-        } else {
-            self.url.clone()
+    /// 查找感知哈希与给定值的汉明距离不超过 distance 的图片，结果按距离从小到大排列
+    ///
+    /// NOTE: 由于索引是按 16 位分段建立的，distance 大于 3 时可能会有遗漏
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn search_by_phash(phash: i64, distance: u32) -> Result<Vec<(Self, u32)>> {
+        let [b0, b1, b2, b3] = bands(phash);
+        let candidates = sqlx::query_as!(
+            Self,
+            r#"
+            SELECT id as "id: u32", hash, sha1, phash, flag as "flag: ImageFlag", storage, key, url, size, etag FROM image
+            WHERE phash_0 = ? OR phash_1 = ? OR phash_2 = ? OR phash_3 = ?
+            "#,
+            b0,
+            b1,
+            b2,
+            b3,
+        )
+        .fetch_all(&*DB)
+        .await?;
+        let mut result = candidates
+            .into_iter()
+            .filter_map(|img| {
+                let d = hamming(img.phash?, phash);
+                (d <= distance).then_some((img, d))
+            })
+            .collect::<Vec<_>>();
+        result.sort_by_key(|(_, d)| *d);
+        Ok(result)
+    }
+
+    /// 按 ID 顺序列出 ID 大于 after 且仍然存放在 telegraph 上的图片
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn list_legacy(after: u32, limit: i32) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"
+            SELECT id as "id: u32", hash, sha1, phash, flag as "flag: ImageFlag", storage, key, url, size, etag FROM image
+            WHERE storage = 'telegraph' AND id > ?
+            ORDER BY id LIMIT ?
+            "#,
+            after,
+            limit,
+        )
+        .fetch_all(&*DB)
+        .await
+    }
+
+    /// 按 ID 顺序列出 ID 大于 after 且还没有对象键的旧图片
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn list_without_key(after: u32, limit: i32) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"
+            SELECT id as "id: u32", hash, sha1, phash, flag as "flag: ImageFlag", storage, key, url, size, etag FROM image
+            WHERE key = '' AND id > ?
+            ORDER BY id LIMIT ?
+            "#,
+            after,
+            limit,
+        )
+        .fetch_all(&*DB)
+        .await
+    }
+
+    /// 按 ID 顺序列出 ID 大于 after 且没有感知哈希的图片
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn list_without_phash(after: u32, limit: i32) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"
+            SELECT id as "id: u32", hash, sha1, phash, flag as "flag: ImageFlag", storage, key, url, size, etag FROM image
+            WHERE phash IS NULL AND id > ?
+            ORDER BY id LIMIT ?
+            "#,
+            after,
+            limit,
+        )
+        .fetch_all(&*DB)
+        .await
+    }
+
+    /// 更新图片的感知哈希
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn update_phash(id: u32, phash: i64) -> Result<SqliteQueryResult> {
+        sqlx::query!("UPDATE image SET phash = ? WHERE id = ?", phash, id).execute(&*DB).await
+    }
+
+    /// 更新图片标记
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn update_flag(id: u32, flag: ImageFlag) -> Result<SqliteQueryResult> {
+        sqlx::query!("UPDATE image SET flag = ? WHERE id = ?", flag, id).execute(&*DB).await
+    }
+
+    /// 记录图片文件的大小和 ETag
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn update_integrity(
+        id: u32,
+        size: i64,
+        etag: Option<&str>,
+    ) -> Result<SqliteQueryResult> {
+        sqlx::query!("UPDATE image SET size = ?, etag = ? WHERE id = ?", size, etag, id)
+            .execute(&*DB)
+            .await
+    }
+
+    /// 更新图片所在的存储后端和对象键，并清除已知的大小和 ETag
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn update_key(id: u32, storage: &str, key: &str) -> Result<SqliteQueryResult> {
+        sqlx::query!(
+            "UPDATE image SET storage = ?, key = ?, size = NULL, etag = NULL WHERE id = ?",
+            storage,
+            key,
+            id
+        )
+        .execute(&*DB)
+        .await
+    }
+
+    /// 图片的公开访问地址
+    pub fn url(&self, storage: &StorageBackend) -> String {
+        self.urls(storage).swap_remove(0)
+    }
+
+    /// 图片所有的公开访问地址，主地址在前，镜像地址在后
+    pub fn urls(&self, storage: &StorageBackend) -> Vec<String> {
+        match self.key.is_empty() {
+            true => vec![self.url.clone()],
+            false => storage.image_urls(&self.storage, &self.key),
         }
     }
 }
@@ -89,6 +288,38 @@ impl PageEntity {
         .await
     }
 
+    /// 获取使用了指定图片的所有页面
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn get_by_image(image_id: u32) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"
+            SELECT gallery_id as "gallery_id: i32", page as "page: i32", image_id as "image_id: u32"
+            FROM page WHERE image_id = ?
+            ORDER BY gallery_id DESC
+            "#,
+            image_id
+        )
+        .fetch_all(&*DB)
+        .await
+    }
+
+    /// 获取指定画廊的某一页
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn get(gallery_id: i32, page: i32) -> Result<Option<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"
+            SELECT gallery_id as "gallery_id: i32", page as "page: i32", image_id as "image_id: u32"
+            FROM page WHERE gallery_id = ? AND page = ?
+            "#,
+            gallery_id,
+            page
+        )
+        .fetch_optional(&*DB)
+        .await
+    }
+
     /// 统计某个画廊的有记录页面数量
     pub async fn count(gallery_id: i32) -> Result<i32> {
         sqlx::query_scalar!("SELECT COUNT(*) FROM page WHERE gallery_id = ?", gallery_id)
@@ -96,3 +327,26 @@ impl PageEntity {
             .await
     }
 }
+
+impl FromStr for ImageFlag {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "ok" => Ok(Self::Ok),
+            "broken" => Ok(Self::Broken),
+            "ad" => Ok(Self::Ad),
+            _ => Err(anyhow!("无效的图片标记：{}", s)),
+        }
+    }
+}
+
+impl ImageFlag {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Broken => "broken",
+            Self::Ad => "ad",
+        }
+    }
+}
@@ -3,7 +3,6 @@ use sqlx::sqlite::SqliteQueryResult;
 use sqlx::Result;
 
 use super::db::DB;
-use crate::config::CHANNEL_ID;
 
 #[derive(sqlx::FromRow, Debug)]
 pub struct InviteLink {
@@ -14,8 +13,7 @@ pub struct InviteLink {
 }
 
 impl InviteLink {
-    pub async fn create(user_id: i64, link: &str) -> Result<SqliteQueryResult> {
-        let channel_id = CHANNEL_ID.get().unwrap();
+    pub async fn create(channel_id: &str, user_id: i64, link: &str) -> Result<SqliteQueryResult> {
         let now = Utc::now().naive_utc();
         sqlx::query!(
             "INSERT INTO invite_link (user_id, chat_id, link, created_at) VALUES (?, ?, ?, ?)",
@@ -28,8 +26,7 @@ impl InviteLink {
         .await
     }
 
-    pub async fn get(user_id: i64) -> Result<Option<InviteLink>> {
-        let channel_id = CHANNEL_ID.get().unwrap();
+    pub async fn get(channel_id: &str, user_id: i64) -> Result<Option<InviteLink>> {
         sqlx::query_as!(InviteLink, "SELECT * FROM invite_link WHERE user_id = ? AND chat_id = ? ORDER BY created_at DESC LIMIT 1", user_id, channel_id)
             .fetch_optional(&*DB)
             .await
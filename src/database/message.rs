@@ -1,10 +1,19 @@
-use chrono::{NaiveDate, Utc};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
 use sqlx::sqlite::SqliteQueryResult;
 use sqlx::Result;
 use tracing::Level;
 
 use super::db::DB;
-use crate::config::CHANNEL_ID;
+
+/// 频道消息的类型，决定了之后如何编辑这条消息
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(rename_all = "lowercase")]
+pub enum MessageKind {
+    /// 纯文本消息
+    Text,
+    /// 以封面作为图片、正文作为说明的图片消息
+    Photo,
+}
 
 #[derive(sqlx::FromRow, Debug)]
 pub struct MessageEntity {
@@ -16,18 +25,29 @@ pub struct MessageEntity {
     pub gallery_id: i32,
     /// 消息发布日期
     pub publish_date: NaiveDate,
+    /// 消息类型
+    pub kind: MessageKind,
+    /// 自动转发到讨论组后，在讨论组中的消息 ID
+    pub thread_id: Option<i32>,
 }
 
 impl MessageEntity {
     #[tracing::instrument(level = Level::DEBUG)]
-    pub async fn create(id: i32, gid: i32) -> Result<SqliteQueryResult> {
-        let channel_id = CHANNEL_ID.get().unwrap();
-        let now = Utc::now().date_naive();
+    pub async fn create(
+        id: i32,
+        channel_id: &str,
+        gid: i32,
+        kind: MessageKind,
+    ) -> Result<SqliteQueryResult> {
+        let now = Utc::now().naive_utc();
+        let date = now.date();
         sqlx::query!(
-            "INSERT INTO message (id, channel_id, gallery_id, publish_date) VALUES (?, ?, ?, ?)",
+            "INSERT INTO message (id, channel_id, gallery_id, publish_date, kind, created_at) VALUES (?, ?, ?, ?, ?, ?)",
             id,
             channel_id,
             gid,
+            date,
+            kind,
             now,
         )
         .execute(&*DB)
@@ -36,8 +56,7 @@ impl MessageEntity {
 
     // TODO: 如果存在与否不重要，其实不需要返回 Option，否则反而不方便上抛错误
     #[tracing::instrument(level = Level::DEBUG)]
-    pub async fn get(id: i32) -> Result<Option<MessageEntity>> {
-        let channel_id = CHANNEL_ID.get().unwrap();
+    pub async fn get(channel_id: &str, id: i32) -> Result<Option<MessageEntity>> {
         sqlx::query_as!(
             MessageEntity,
             r#"
@@ -45,7 +64,9 @@ impl MessageEntity {
                 id as "id: i32",
                 channel_id,
                 gallery_id as "gallery_id: i32",
-                publish_date
+                publish_date,
+                kind as "kind: MessageKind",
+                thread_id as "thread_id: i32"
             FROM message WHERE id = ? AND channel_id = ?
             "#,
             id,
@@ -56,16 +77,32 @@ impl MessageEntity {
     }
 
     #[tracing::instrument(level = Level::DEBUG)]
-    pub async fn delete(id: i32) -> Result<SqliteQueryResult> {
-        let channel_id = CHANNEL_ID.get().unwrap();
+    pub async fn delete(channel_id: &str, id: i32) -> Result<SqliteQueryResult> {
         sqlx::query!("DELETE FROM message WHERE id = ? AND channel_id = ?", id, channel_id)
             .execute(&*DB)
             .await
     }
 
+    /// 记录频道消息在讨论组中对应的消息 ID
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn set_thread(
+        channel_id: &str,
+        id: i32,
+        thread_id: i32,
+    ) -> Result<SqliteQueryResult> {
+        sqlx::query!(
+            "UPDATE message SET thread_id = ? WHERE id = ? AND channel_id = ?",
+            thread_id,
+            id,
+            channel_id
+        )
+        .execute(&*DB)
+        .await
+    }
+
+    /// 获取画廊在任意频道中最近的一条消息
     #[tracing::instrument(level = Level::DEBUG)]
     pub async fn get_by_gallery(gid: i32) -> Result<Option<MessageEntity>> {
-        let channel_id = CHANNEL_ID.get().unwrap();
         sqlx::query_as!(
             MessageEntity,
             r#"
@@ -73,7 +110,32 @@ impl MessageEntity {
                 id as "id: i32",
                 channel_id,
                 gallery_id as "gallery_id: i32",
-                publish_date
+                publish_date,
+                kind as "kind: MessageKind",
+                thread_id as "thread_id: i32"
+            FROM message
+            WHERE gallery_id = ?
+            ORDER BY publish_date DESC
+            "#,
+            gid,
+        )
+        .fetch_optional(&*DB)
+        .await
+    }
+
+    /// 获取画廊在指定频道中最近的一条消息
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn get_by_gallery_in(channel_id: &str, gid: i32) -> Result<Option<MessageEntity>> {
+        sqlx::query_as!(
+            MessageEntity,
+            r#"
+            SELECT
+                id as "id: i32",
+                channel_id,
+                gallery_id as "gallery_id: i32",
+                publish_date,
+                kind as "kind: MessageKind",
+                thread_id as "thread_id: i32"
             FROM message
             WHERE gallery_id = ? AND channel_id = ?
             ORDER BY publish_date DESC
@@ -84,4 +146,35 @@ impl MessageEntity {
         .fetch_optional(&*DB)
         .await
     }
+
+    /// 最近一次发送频道消息的时间，旧数据没有记录发送时间
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn last_created() -> Result<Option<NaiveDateTime>> {
+        sqlx::query_scalar!(r#"SELECT MAX(created_at) as "created_at: NaiveDateTime" FROM message"#)
+            .fetch_one(&*DB)
+            .await
+    }
+
+    /// 列出画廊在所有频道中的消息，按发布时间排列
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn list_by_gallery(gid: i32) -> Result<Vec<MessageEntity>> {
+        sqlx::query_as!(
+            MessageEntity,
+            r#"
+            SELECT
+                id as "id: i32",
+                channel_id,
+                gallery_id as "gallery_id: i32",
+                publish_date,
+                kind as "kind: MessageKind",
+                thread_id as "thread_id: i32"
+            FROM message
+            WHERE gallery_id = ?
+            ORDER BY publish_date
+            "#,
+            gid,
+        )
+        .fetch_all(&*DB)
+        .await
+    }
 }
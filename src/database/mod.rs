@@ -1,16 +1,32 @@
 mod challenge;
+mod contact_sheet;
 mod db;
 mod gallery;
+mod history;
 mod image;
 mod invite_link;
 mod message;
 mod poll;
+mod queue;
+mod relation;
+mod review;
+mod schedule;
+mod stats;
 mod telegraph;
+mod telegraph_account;
 
 pub use challenge::*;
+pub use contact_sheet::*;
 pub use gallery::*;
+pub use history::*;
 pub use image::*;
 pub use invite_link::*;
 pub use message::*;
 pub use poll::*;
+pub use queue::*;
+pub use relation::*;
+pub use review::*;
+pub use schedule::*;
+pub use stats::*;
 pub use telegraph::*;
+pub use telegraph_account::*;
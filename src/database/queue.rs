@@ -0,0 +1,117 @@
+use chrono::{NaiveDateTime, Utc};
+use sqlx::sqlite::SqliteQueryResult;
+use sqlx::Result;
+use tracing::Level;
+
+use super::db::DB;
+
+/// 已经准备好文章、等待按计划发布的画廊
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct QueueEntity {
+    /// 画廊 ID
+    pub gallery_id: i32,
+    /// 发布顺序，越小越先发布
+    pub position: i32,
+    /// 加入队列的时间
+    pub created_at: NaiveDateTime,
+    /// 发布时间，尚未发布时为空
+    pub published_at: Option<NaiveDateTime>,
+    /// 加入队列时的画廊信息，JSON 格式，发布时直接使用，不再重新获取
+    pub gallery: Option<String>,
+}
+
+impl QueueEntity {
+    /// 加入队列末尾，如果已存在则忽略
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn push(gallery_id: i32, gallery: &str) -> Result<SqliteQueryResult> {
+        let now = Utc::now().naive_utc();
+        sqlx::query!(
+            "INSERT OR IGNORE INTO publish_queue (gallery_id, position, created_at, gallery)
+            VALUES (?, (SELECT IFNULL(MAX(position), 0) + 1 FROM publish_queue), ?, ?)",
+            gallery_id,
+            now,
+            gallery
+        )
+        .execute(&*DB)
+        .await
+    }
+
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn get(gallery_id: i32) -> Result<Option<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"SELECT
+                gallery_id as "gallery_id: i32",
+                position as "position: i32",
+                created_at,
+                published_at,
+                gallery
+            FROM publish_queue WHERE gallery_id = ?"#,
+            gallery_id
+        )
+        .fetch_optional(&*DB)
+        .await
+    }
+
+    /// 按发布顺序列出所有等待发布的画廊
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn list_pending() -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"SELECT
+                gallery_id as "gallery_id: i32",
+                position as "position: i32",
+                created_at,
+                published_at,
+                gallery
+            FROM publish_queue WHERE published_at IS NULL ORDER BY position"#
+        )
+        .fetch_all(&*DB)
+        .await
+    }
+
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn set_position(gallery_id: i32, position: i32) -> Result<SqliteQueryResult> {
+        sqlx::query!(
+            "UPDATE publish_queue SET position = ? WHERE gallery_id = ?",
+            position,
+            gallery_id
+        )
+        .execute(&*DB)
+        .await
+    }
+
+    /// 移到队列末尾
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn move_to_end(gallery_id: i32) -> Result<SqliteQueryResult> {
+        sqlx::query!(
+            "UPDATE publish_queue SET position = (SELECT MAX(position) + 1 FROM publish_queue) WHERE gallery_id = ?",
+            gallery_id
+        )
+        .execute(&*DB)
+        .await
+    }
+
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn mark_published(gallery_id: i32) -> Result<SqliteQueryResult> {
+        let now = Utc::now().naive_utc();
+        sqlx::query!(
+            "UPDATE publish_queue SET published_at = ? WHERE gallery_id = ?",
+            now,
+            gallery_id
+        )
+        .execute(&*DB)
+        .await
+    }
+
+    /// 统计在指定时间之后从队列中发布的数量
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn count_published_since(time: NaiveDateTime) -> Result<i32> {
+        sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count: i32" FROM publish_queue WHERE published_at >= ?"#,
+            time
+        )
+        .fetch_one(&*DB)
+        .await
+    }
+}
@@ -0,0 +1,140 @@
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteQueryResult;
+use sqlx::Result;
+use tracing::Level;
+
+use super::db::DB;
+
+/// 画廊之间的关系
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum RelationKind {
+    /// 疑似重复，等待管理员决定
+    Pending,
+    /// 重复画廊，不会发布
+    Duplicate,
+    /// 同一作品的另一个版本，发布时会回复在原画廊的消息下
+    Version,
+    /// 管理员确认不是重复，作为独立画廊发布
+    Distinct,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct GalleryRelationEntity {
+    /// 画廊 ID
+    pub gallery_id: i32,
+    /// 与之相关的更早的画廊 ID
+    pub related_id: i32,
+    /// 关系类型
+    pub kind: RelationKind,
+    /// 页面重合度，为 0~1 的小数
+    pub overlap: f32,
+    /// 创建时间
+    pub created_at: NaiveDateTime,
+}
+
+impl RelationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Duplicate => "duplicate",
+            Self::Version => "version",
+            Self::Distinct => "distinct",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "pending" => Some(Self::Pending),
+            "duplicate" => Some(Self::Duplicate),
+            "version" => Some(Self::Version),
+            "distinct" => Some(Self::Distinct),
+            _ => None,
+        }
+    }
+}
+
+impl GalleryRelationEntity {
+    /// 创建一条记录，如果已存在则覆盖
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn create(
+        gallery_id: i32,
+        related_id: i32,
+        kind: RelationKind,
+        overlap: f32,
+    ) -> Result<SqliteQueryResult> {
+        let now = Utc::now().naive_utc();
+        sqlx::query!(
+            "REPLACE INTO gallery_relation (gallery_id, related_id, kind, overlap, created_at) VALUES (?, ?, ?, ?, ?)",
+            gallery_id,
+            related_id,
+            kind,
+            overlap,
+            now,
+        )
+        .execute(&*DB)
+        .await
+    }
+
+    /// 获取指定画廊与更早画廊之间的关系
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn get(gallery_id: i32) -> Result<Option<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"
+            SELECT
+                gallery_id as "gallery_id: i32",
+                related_id as "related_id: i32",
+                kind as "kind: RelationKind",
+                overlap as "overlap: f32",
+                created_at
+            FROM gallery_relation WHERE gallery_id = ?
+            ORDER BY created_at DESC
+            "#,
+            gallery_id
+        )
+        .fetch_optional(&*DB)
+        .await
+    }
+
+    /// 获取与指定画廊相关的所有画廊，包括作为较早画廊和较新画廊两种情况
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn list(gallery_id: i32) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"
+            SELECT
+                gallery_id as "gallery_id: i32",
+                related_id as "related_id: i32",
+                kind as "kind: RelationKind",
+                overlap as "overlap: f32",
+                created_at
+            FROM gallery_relation WHERE gallery_id = ? OR related_id = ?
+            ORDER BY created_at
+            "#,
+            gallery_id,
+            gallery_id
+        )
+        .fetch_all(&*DB)
+        .await
+    }
+
+    /// 更新关系类型
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn update_kind(
+        gallery_id: i32,
+        related_id: i32,
+        kind: RelationKind,
+    ) -> Result<SqliteQueryResult> {
+        sqlx::query!(
+            "UPDATE gallery_relation SET kind = ? WHERE gallery_id = ? AND related_id = ?",
+            kind,
+            gallery_id,
+            related_id
+        )
+        .execute(&*DB)
+        .await
+    }
+}
@@ -0,0 +1,176 @@
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteQueryResult;
+use sqlx::Result;
+use tracing::Level;
+
+use super::db::DB;
+
+/// 审核状态
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewStatus {
+    /// 等待管理员处理
+    Pending,
+    /// 已批准，会立即发布
+    Approved,
+    /// 已拒绝，不会再次处理
+    Rejected,
+    /// 超时未处理，不会再次处理
+    Expired,
+    /// 被上传规则跳过，不会再次处理
+    Skipped,
+}
+
+impl ReviewStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Approved => "approved",
+            Self::Rejected => "rejected",
+            Self::Expired => "expired",
+            Self::Skipped => "skipped",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "pending" => Some(Self::Pending),
+            "approved" => Some(Self::Approved),
+            "rejected" => Some(Self::Rejected),
+            "expired" => Some(Self::Expired),
+            "skipped" => Some(Self::Skipped),
+            _ => None,
+        }
+    }
+}
+
+/// 被上传规则暂缓发布、等待管理员处理的画廊，被规则跳过的画廊也会记录在这里
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct ReviewEntity {
+    /// 画廊 ID
+    pub gallery_id: i32,
+    /// 命中的规则名称
+    pub rule: String,
+    /// 创建时间
+    pub created_at: NaiveDateTime,
+    /// 审核状态
+    pub status: ReviewStatus,
+    /// 管理员批准时填写的附言，会展示在频道消息中
+    pub note: Option<String>,
+    /// 讨论组中审核消息的 ID
+    pub message_id: i32,
+    /// 处理时间
+    pub decided_at: Option<NaiveDateTime>,
+}
+
+impl ReviewEntity {
+    /// 创建一条记录，如果已存在则覆盖
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn create(gallery_id: i32, rule: &str, message_id: i32) -> Result<SqliteQueryResult> {
+        let now = Utc::now().naive_utc();
+        sqlx::query!(
+            "REPLACE INTO review (gallery_id, rule, created_at, status, message_id) VALUES (?, ?, ?, 'pending', ?)",
+            gallery_id,
+            rule,
+            now,
+            message_id
+        )
+        .execute(&*DB)
+        .await
+    }
+
+    /// 记录被上传规则跳过的画廊，之后扫描到时不再重复获取画廊信息
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn skip(gallery_id: i32, rule: &str) -> Result<SqliteQueryResult> {
+        let now = Utc::now().naive_utc();
+        sqlx::query!(
+            "INSERT OR IGNORE INTO review (gallery_id, rule, created_at, status, message_id, decided_at) VALUES (?, ?, ?, 'skipped', 0, ?)",
+            gallery_id,
+            rule,
+            now,
+            now
+        )
+        .execute(&*DB)
+        .await
+    }
+
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn get(gallery_id: i32) -> Result<Option<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"SELECT
+                gallery_id as "gallery_id: i32",
+                rule,
+                created_at,
+                status as "status: ReviewStatus",
+                note,
+                message_id as "message_id: i32",
+                decided_at
+            FROM review WHERE gallery_id = ?"#,
+            gallery_id
+        )
+        .fetch_optional(&*DB)
+        .await
+    }
+
+    /// 根据讨论组中的审核消息查找
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn get_by_message(message_id: i32) -> Result<Option<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"SELECT
+                gallery_id as "gallery_id: i32",
+                rule,
+                created_at,
+                status as "status: ReviewStatus",
+                note,
+                message_id as "message_id: i32",
+                decided_at
+            FROM review WHERE message_id = ?"#,
+            message_id
+        )
+        .fetch_optional(&*DB)
+        .await
+    }
+
+    /// 列出在指定时间之前创建、仍在等待处理的审核
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn list_pending_before(time: NaiveDateTime) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"SELECT
+                gallery_id as "gallery_id: i32",
+                rule,
+                created_at,
+                status as "status: ReviewStatus",
+                note,
+                message_id as "message_id: i32",
+                decided_at
+            FROM review WHERE status = 'pending' AND created_at < ?"#,
+            time
+        )
+        .fetch_all(&*DB)
+        .await
+    }
+
+    /// 处理一条等待中的审核，已经处理过的审核不会被修改，此时影响行数为 0
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn decide(
+        gallery_id: i32,
+        status: ReviewStatus,
+        note: Option<&str>,
+    ) -> Result<SqliteQueryResult> {
+        let now = Utc::now().naive_utc();
+        sqlx::query!(
+            "UPDATE review SET status = ?, note = ?, decided_at = ? WHERE gallery_id = ? AND status = 'pending'",
+            status,
+            note,
+            now,
+            gallery_id
+        )
+        .execute(&*DB)
+        .await
+    }
+}
@@ -0,0 +1,123 @@
+use chrono::NaiveDateTime;
+use sqlx::sqlite::SqliteQueryResult;
+use sqlx::Result;
+use tracing::Level;
+
+use super::db::DB;
+
+/// 已发布画廊的元数据更新计划
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct UpdateScheduleEntity {
+    /// 画廊 ID
+    pub gallery_id: i32,
+    /// 下一次检查的时间
+    pub next_check_at: NaiveDateTime,
+    /// 连续没有发现变化的检查次数
+    pub unchanged: i32,
+    /// 管理员指定的检查间隔，单位为小时，为空时根据画廊的发布时长和变化情况自动计算
+    pub interval_hours: Option<i32>,
+    /// 上一次检查的时间
+    pub last_checked_at: Option<NaiveDateTime>,
+}
+
+impl UpdateScheduleEntity {
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn get(gallery_id: i32) -> Result<Option<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"SELECT
+                gallery_id as "gallery_id: i32",
+                next_check_at,
+                unchanged as "unchanged: i32",
+                interval_hours as "interval_hours: i32",
+                last_checked_at
+            FROM update_schedule WHERE gallery_id = ?"#,
+            gallery_id
+        )
+        .fetch_optional(&*DB)
+        .await
+    }
+
+    /// 按检查时间列出已经到期的画廊，会跳过被标记为删除的画廊
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn list_due(now: NaiveDateTime, limit: i32) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"SELECT
+                update_schedule.gallery_id as "gallery_id: i32",
+                update_schedule.next_check_at,
+                update_schedule.unchanged as "unchanged: i32",
+                update_schedule.interval_hours as "interval_hours: i32",
+                update_schedule.last_checked_at
+            FROM update_schedule
+            JOIN gallery ON gallery.id = update_schedule.gallery_id
+            WHERE update_schedule.next_check_at <= ? AND gallery.deleted = FALSE
+            ORDER BY update_schedule.next_check_at
+            LIMIT ?"#,
+            now,
+            limit
+        )
+        .fetch_all(&*DB)
+        .await
+    }
+
+    /// 记录一次检查的结果，并安排下一次检查，不会修改管理员指定的检查间隔
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn checked(
+        gallery_id: i32,
+        now: NaiveDateTime,
+        next_check_at: NaiveDateTime,
+        unchanged: i32,
+    ) -> Result<SqliteQueryResult> {
+        sqlx::query!(
+            "INSERT INTO update_schedule (gallery_id, next_check_at, unchanged, last_checked_at)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT (gallery_id) DO UPDATE SET
+                next_check_at = excluded.next_check_at,
+                unchanged = excluded.unchanged,
+                last_checked_at = excluded.last_checked_at",
+            gallery_id,
+            next_check_at,
+            unchanged,
+            now
+        )
+        .execute(&*DB)
+        .await
+    }
+
+    /// 安排画廊的第一次检查，已有计划时忽略
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn create(
+        gallery_id: i32,
+        next_check_at: NaiveDateTime,
+    ) -> Result<SqliteQueryResult> {
+        sqlx::query!(
+            "INSERT OR IGNORE INTO update_schedule (gallery_id, next_check_at) VALUES (?, ?)",
+            gallery_id,
+            next_check_at
+        )
+        .execute(&*DB)
+        .await
+    }
+
+    /// 设置或清除管理员指定的检查间隔，同时调整下一次检查的时间
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn set_interval(
+        gallery_id: i32,
+        interval_hours: Option<i32>,
+        next_check_at: NaiveDateTime,
+    ) -> Result<SqliteQueryResult> {
+        sqlx::query!(
+            "INSERT INTO update_schedule (gallery_id, next_check_at, interval_hours)
+            VALUES (?, ?, ?)
+            ON CONFLICT (gallery_id) DO UPDATE SET
+                next_check_at = excluded.next_check_at,
+                interval_hours = excluded.interval_hours",
+            gallery_id,
+            next_check_at,
+            interval_hours
+        )
+        .execute(&*DB)
+        .await
+    }
+}
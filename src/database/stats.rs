@@ -0,0 +1,203 @@
+use chrono::NaiveDateTime;
+use sqlx::sqlite::SqliteQueryResult;
+use sqlx::Result;
+use tracing::Level;
+
+use super::db::DB;
+use super::PollEntity;
+use crate::ehentai::EhGallery;
+
+/// 画廊在某一时刻的热度快照，每次更新元数据时记录一条
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct GalleryStatsEntity {
+    pub id: i64,
+    /// 画廊 ID
+    pub gallery_id: i32,
+    /// E 站收藏数
+    pub favorite: i32,
+    /// E 站评分，0~5
+    pub rating: Option<f32>,
+    /// E 站评分人数
+    pub rating_count: i32,
+    /// 本站投票的分数，为 0~1 的小数
+    pub score: Option<f32>,
+    /// 本站投票人数
+    pub votes: i32,
+    /// 记录时间
+    pub created_at: NaiveDateTime,
+}
+
+/// 画廊在一段时间内的热度变化，由这段时间内的第一条和最后一条快照计算
+#[derive(Debug, Clone)]
+pub struct GalleryGrowth {
+    pub start: GalleryStatsEntity,
+    pub end: GalleryStatsEntity,
+}
+
+impl GalleryGrowth {
+    /// 两条快照之间相隔的天数
+    pub fn days(&self) -> f64 {
+        (self.end.created_at - self.start.created_at).num_seconds() as f64 / 86400.
+    }
+
+    /// 平均每天增加的收藏数
+    pub fn favorite_rate(&self) -> f64 {
+        self.rate(self.end.favorite - self.start.favorite)
+    }
+
+    /// 平均每天增加的 E 站评分人数
+    pub fn rating_count_rate(&self) -> f64 {
+        self.rate(self.end.rating_count - self.start.rating_count)
+    }
+
+    /// 平均每天增加的本站投票人数
+    pub fn votes_rate(&self) -> f64 {
+        self.rate(self.end.votes - self.start.votes)
+    }
+
+    /// 本站投票分数的变化
+    pub fn score_delta(&self) -> Option<f32> {
+        Some(self.end.score? - self.start.score?)
+    }
+
+    fn rate(&self, delta: i32) -> f64 {
+        match self.days() {
+            d if d > 0. => delta as f64 / d,
+            _ => 0.,
+        }
+    }
+}
+
+impl GalleryStatsEntity {
+    /// 记录画廊当前的热度，本站投票的数据从数据库中读取
+    #[tracing::instrument(level = Level::DEBUG, skip(gallery))]
+    pub async fn create(gallery: &EhGallery, now: NaiveDateTime) -> Result<SqliteQueryResult> {
+        let id = gallery.url.id();
+        let (score, votes) = match PollEntity::get_by_gallery(id).await? {
+            Some(poll) => (Some(poll.score), PollEntity::get_vote(poll.id).await?.iter().sum()),
+            None => (None, 0),
+        };
+        sqlx::query!(
+            "INSERT INTO gallery_stats
+            (gallery_id, favorite, rating, rating_count, score, votes, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)",
+            id,
+            gallery.favorite,
+            gallery.rating,
+            gallery.rating_count,
+            score,
+            votes,
+            now,
+        )
+        .execute(&*DB)
+        .await
+    }
+
+    /// 按时间顺序列出画廊自指定时间以来的快照
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn list(gallery_id: i32, since: NaiveDateTime) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"SELECT
+                id,
+                gallery_id as "gallery_id: i32",
+                favorite as "favorite: i32",
+                rating as "rating: f32",
+                rating_count as "rating_count: i32",
+                score as "score: f32",
+                votes as "votes: i32",
+                created_at
+            FROM gallery_stats
+            WHERE gallery_id = ? AND created_at >= ?
+            ORDER BY created_at"#,
+            gallery_id,
+            since
+        )
+        .fetch_all(&*DB)
+        .await
+    }
+
+    /// 画廊自指定时间以来的热度变化，快照少于两条时返回 None
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn growth(gallery_id: i32, since: NaiveDateTime) -> Result<Option<GalleryGrowth>> {
+        let list = Self::list(gallery_id, since).await?;
+        Ok(match (list.first(), list.last()) {
+            (Some(start), Some(end)) if list.len() > 1 => {
+                Some(GalleryGrowth { start: start.clone(), end: end.clone() })
+            }
+            _ => None,
+        })
+    }
+
+    /// 自指定时间以来所有画廊的热度变化，按每天增加的收藏数从高到低排列
+    ///
+    /// 会跳过被标记为删除的画廊，以及快照少于两条的画廊
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn trending(since: NaiveDateTime, limit: usize) -> Result<Vec<GalleryGrowth>> {
+        let rows = sqlx::query_as!(
+            Self,
+            r#"SELECT
+                gallery_stats.id,
+                gallery_stats.gallery_id as "gallery_id: i32",
+                gallery_stats.favorite as "favorite: i32",
+                gallery_stats.rating as "rating: f32",
+                gallery_stats.rating_count as "rating_count: i32",
+                gallery_stats.score as "score: f32",
+                gallery_stats.votes as "votes: i32",
+                gallery_stats.created_at
+            FROM gallery_stats
+            JOIN gallery ON gallery.id = gallery_stats.gallery_id
+            WHERE gallery_stats.created_at >= ? AND gallery.deleted = FALSE
+            ORDER BY gallery_stats.gallery_id, gallery_stats.created_at"#,
+            since
+        )
+        .fetch_all(&*DB)
+        .await?;
+
+        let mut result = vec![];
+        for group in rows.chunk_by(|a, b| a.gallery_id == b.gallery_id) {
+            if let [start, .., end] = group {
+                result.push(GalleryGrowth { start: start.clone(), end: end.clone() });
+            }
+        }
+        result.sort_by(|a, b| b.favorite_rate().total_cmp(&a.favorite_rate()));
+        result.truncate(limit);
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn snapshot(favorite: i32, votes: i32, created_at: NaiveDateTime) -> GalleryStatsEntity {
+        GalleryStatsEntity {
+            id: 0,
+            gallery_id: 1,
+            favorite,
+            rating: Some(4.5),
+            rating_count: 10,
+            score: Some(0.8),
+            votes,
+            created_at,
+        }
+    }
+
+    #[test]
+    fn growth_rates() {
+        let now = NaiveDateTime::default();
+        let growth = GalleryGrowth {
+            start: snapshot(100, 4, now),
+            end: snapshot(400, 10, now + Duration::hours(36)),
+        };
+        assert_eq!(growth.favorite_rate(), 200.);
+        assert_eq!(growth.votes_rate(), 4.);
+        assert_eq!(growth.rating_count_rate(), 0.);
+        assert_eq!(growth.score_delta(), Some(0.));
+
+        let same = GalleryGrowth { start: snapshot(100, 4, now), end: snapshot(200, 4, now) };
+        assert_eq!(same.favorite_rate(), 0.);
+    }
+}
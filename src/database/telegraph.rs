@@ -1,22 +1,49 @@
+use std::ops::Deref;
+
+use sqlx::database::HasValueRef;
+use sqlx::error::BoxDynError;
+use sqlx::prelude::*;
 use sqlx::sqlite::SqliteQueryResult;
-use sqlx::Result;
+use sqlx::{Database, Result, Sqlite};
 
 use super::db::DB;
 
+/// 多篇文章的地址或路径，使用 JSON 数组存储
+#[derive(Debug, Clone, Default)]
+pub struct TelegraphParts(pub Vec<String>);
+
+/// 画廊的预览页面，根据配置可能发布在 telegraph 或者自建的静态页面上
 #[derive(sqlx::FromRow, Debug)]
 pub struct TelegraphEntity {
     /// 画廊 ID
     pub gallery_id: i32,
-    /// telegraph 文章 URL
+    /// telegraph 文章 URL，如果文章被拆分为多篇，则为第一篇的 URL
     pub url: String,
+    /// telegraph 文章路径，用于原地编辑文章，如果文章被拆分为多篇，则为第一篇的路径
+    pub path: String,
+    /// 所有分篇的 URL，旧数据以及未拆分的文章可能为空
+    pub parts: TelegraphParts,
+    /// 所有分篇的路径，顺序与 parts 一致，旧数据可能为空
+    pub paths: TelegraphParts,
 }
 
 impl TelegraphEntity {
-    pub async fn create(gallery_id: i32, telegraph: &str) -> Result<SqliteQueryResult> {
+    /// 记录画廊的文章，parts 和 paths 分别为各分篇的 URL 和路径，第一篇同时记录在 url 和 path 中
+    pub async fn create(
+        gallery_id: i32,
+        parts: &[String],
+        paths: &[String],
+    ) -> Result<SqliteQueryResult> {
+        let (telegraph, path) = (&parts[0], &paths[0]);
+        let parts = serde_json::to_string(parts).unwrap();
+        let paths = serde_json::to_string(paths).unwrap();
         sqlx::query!(
-            "REPLACE INTO telegraph (gallery_id, url) VALUES (?, ?)",
+            "REPLACE INTO telegraph (gallery_id, url, path, parts, paths) VALUES (?, ?, ?, ?, ?)",
             gallery_id,
-            telegraph
+            telegraph,
+            path,
+            parts,
+            paths
         )
         .execute(&*DB)
         .await
@@ -25,16 +52,87 @@ impl TelegraphEntity {
     pub async fn get(gallery_id: i32) -> Result<Option<TelegraphEntity>> {
         sqlx::query_as!(
             TelegraphEntity,
-            r#"SELECT gallery_id as "gallery_id: i32", url FROM telegraph WHERE gallery_id = ?"#,
+            r#"SELECT gallery_id as "gallery_id: i32", url, path, parts as "parts: TelegraphParts", paths as "paths: TelegraphParts" FROM telegraph WHERE gallery_id = ?"#,
             gallery_id
         )
         .fetch_optional(&*DB)
         .await
     }
 
-    pub async fn update(gallery_id: i32, telegraph: &str) -> Result<SqliteQueryResult> {
-        sqlx::query!("UPDATE telegraph SET url = ? WHERE gallery_id = ?", telegraph, gallery_id)
-            .execute(&*DB)
-            .await
+    pub async fn update(
+        gallery_id: i32,
+        parts: &[String],
+        paths: &[String],
+    ) -> Result<SqliteQueryResult> {
+        let (telegraph, path) = (&parts[0], &paths[0]);
+        let parts = serde_json::to_string(parts).unwrap();
+        let paths = serde_json::to_string(paths).unwrap();
+        sqlx::query!(
+            "UPDATE telegraph SET url = ?, path = ?, parts = ?, paths = ? WHERE gallery_id = ?",
+            telegraph,
+            path,
+            parts,
+            paths,
+            gallery_id
+        )
+        .execute(&*DB)
+        .await
+    }
+
+    /// 获取所有分篇的 URL，对于没有记录分篇的旧数据，返回文章本身的 URL
+    pub fn urls(&self) -> Vec<String> {
+        if self.parts.is_empty() {
+            vec![self.url.clone()]
+        } else {
+            self.parts.0.clone()
+        }
+    }
+
+    /// 获取所有分篇的路径，顺序与 [`Self::urls`] 一致
+    pub fn paths(&self) -> Vec<String> {
+        if !self.paths.is_empty() {
+            return self.paths.0.clone();
+        }
+        // 没有记录路径的旧数据，除第一篇外只能从 telegraph 的地址中还原
+        let mut paths = self
+            .urls()
+            .iter()
+            .map(|url| url.trim_start_matches("https://telegra.ph/").to_owned())
+            .collect::<Vec<_>>();
+        if !self.path.is_empty() {
+            paths[0] = self.path.clone();
+        }
+        paths
+    }
+}
+
+impl<'q> Decode<'q, Sqlite> for TelegraphParts {
+    fn decode(
+        value: <Sqlite as HasValueRef<'q>>::ValueRef,
+    ) -> std::result::Result<Self, BoxDynError> {
+        let str = <String as Decode<Sqlite>>::decode(value)?;
+        if str.is_empty() {
+            Ok(TelegraphParts(vec![]))
+        } else {
+            Ok(TelegraphParts(serde_json::from_str(&str)?))
+        }
+    }
+}
+
+impl Type<Sqlite> for TelegraphParts {
+    fn type_info() -> <Sqlite as Database>::TypeInfo {
+        <String as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &<Sqlite as Database>::TypeInfo) -> bool {
+        <String as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+impl Deref for TelegraphParts {
+    type Target = Vec<String>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
     }
 }
@@ -0,0 +1,122 @@
+use chrono::prelude::*;
+use sqlx::sqlite::SqliteQueryResult;
+use sqlx::Result;
+use tracing::Level;
+
+use super::db::DB;
+
+/// telegraph 账号池中的一个账号
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct TelegraphAccountEntity {
+    pub id: i64,
+    pub access_token: String,
+    /// 连续发布失败的次数
+    pub failures: i32,
+    /// 是否已停用
+    pub retired: bool,
+    pub created_at: NaiveDateTime,
+}
+
+impl TelegraphAccountEntity {
+    /// 添加一个账号，如果已存在则直接返回
+    #[tracing::instrument(level = Level::DEBUG, skip(access_token))]
+    pub async fn create(access_token: &str) -> Result<Self> {
+        let now = Utc::now().naive_utc();
+        sqlx::query!(
+            "INSERT OR IGNORE INTO telegraph_account (access_token, created_at) VALUES (?, ?)",
+            access_token,
+            now
+        )
+        .execute(&*DB)
+        .await?;
+        sqlx::query_as!(
+            Self,
+            r#"SELECT id, access_token, failures as "failures: i32", retired, created_at FROM telegraph_account WHERE access_token = ?"#,
+            access_token
+        )
+        .fetch_one(&*DB)
+        .await
+    }
+
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn get(id: i64) -> Result<Option<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"SELECT id, access_token, failures as "failures: i32", retired, created_at FROM telegraph_account WHERE id = ?"#,
+            id
+        )
+        .fetch_optional(&*DB)
+        .await
+    }
+
+    /// 列出所有账号，包括已停用的
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn list() -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"SELECT id, access_token, failures as "failures: i32", retired, created_at FROM telegraph_account ORDER BY id"#
+        )
+        .fetch_all(&*DB)
+        .await
+    }
+
+    /// 列出所有可以用于发布新文章的账号
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn list_active() -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"SELECT id, access_token, failures as "failures: i32", retired, created_at FROM telegraph_account WHERE retired = FALSE ORDER BY id"#
+        )
+        .fetch_all(&*DB)
+        .await
+    }
+
+    /// 发布成功，清空失败次数
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn record_success(id: i64) -> Result<SqliteQueryResult> {
+        sqlx::query!("UPDATE telegraph_account SET failures = 0 WHERE id = ?", id)
+            .execute(&*DB)
+            .await
+    }
+
+    /// 发布失败，返回累计的连续失败次数
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn record_failure(id: i64) -> Result<i32> {
+        sqlx::query_scalar!(
+            r#"UPDATE telegraph_account SET failures = failures + 1 WHERE id = ? RETURNING failures as "failures: i32""#,
+            id
+        )
+        .fetch_one(&*DB)
+        .await
+    }
+
+    /// 停用账号
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn retire(id: i64) -> Result<SqliteQueryResult> {
+        sqlx::query!("UPDATE telegraph_account SET retired = TRUE WHERE id = ?", id)
+            .execute(&*DB)
+            .await
+    }
+
+    /// 记录文章所属的账号
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn add_page(id: i64, path: &str) -> Result<SqliteQueryResult> {
+        sqlx::query!("REPLACE INTO telegraph_page (path, account_id) VALUES (?, ?)", path, id)
+            .execute(&*DB)
+            .await
+    }
+
+    /// 获取文章所属的账号，旧文章没有记录时返回 None
+    #[tracing::instrument(level = Level::DEBUG)]
+    pub async fn get_by_page(path: &str) -> Result<Option<Self>> {
+        sqlx::query_as!(
+            Self,
+            r#"SELECT telegraph_account.id, access_token, failures as "failures: i32", retired, created_at
+            FROM telegraph_account JOIN telegraph_page ON telegraph_page.account_id = telegraph_account.id
+            WHERE telegraph_page.path = ?"#,
+            path
+        )
+        .fetch_optional(&*DB)
+        .await
+    }
+}
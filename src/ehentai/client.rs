@@ -159,7 +159,20 @@ impl EhClient {
     pub async fn get_gallery(&self, url: &EhGalleryUrl) -> Result<EhGallery> {
         // NOTE: 由于 Html 是 !Send 的，为了避免它被包含在 Future 上下文中，这里将它放在一个单独的作用域内
         // 参见：https://rust-lang.github.io/async-book/07_workarounds/03_send_approximation.html
-        let (title, title_jp, parent, tags, favorite, mut pages, posted, mut next_page) = {
+        let (
+            title,
+            title_jp,
+            parent,
+            tags,
+            favorite,
+            category,
+            rating,
+            rating_count,
+            mut pages,
+            posted,
+            uploader,
+            mut next_page,
+        ) = {
             let resp = send!(self.0.get(url.url()))?;
             let html = Html::parse_document(&resp.text().await?);
 
@@ -185,17 +198,41 @@ impl EhClient {
             let favorite = html.select_text("#favcount").expect("xpath fail: #favcount");
             let favorite = favorite.split(' ').next().unwrap().parse().unwrap();
 
+            // 分类和评分，尚未评分时显示为 Not Yet Rated
+            let category = html.select_text("#gdc div").unwrap_or_default();
+            let rating_count =
+                html.select_text("#rating_count").and_then(|s| s.parse().ok()).unwrap_or_default();
+            let rating = html
+                .select_text("#rating_label")
+                .and_then(|s| s.strip_prefix("Average: ")?.parse().ok());
+
             // 发布时间
             let posted = &html.select_texts("td.gdt2")[0];
             let posted = NaiveDateTime::parse_from_str(posted, "%Y-%m-%d %H:%M")?;
 
+            // 上传者
+            let uploader = html.select_text("div#gdn a");
+
             // 每一页的 URL
             let pages = html.select_attrs("div#gdt a", "href");
 
             // 下一页的 URL
             let next_page = html.select_attr("table.ptb td:last-child a", "href");
 
-            (title, title_jp, parent, tags, favorite, pages, posted, next_page)
+            (
+                title,
+                title_jp,
+                parent,
+                tags,
+                favorite,
+                category,
+                rating,
+                rating_count,
+                pages,
+                posted,
+                uploader,
+                next_page,
+            )
         };
 
         while let Some(next_page_url) = &next_page {
@@ -220,35 +257,49 @@ impl EhClient {
             parent,
             tags,
             favorite,
+            category,
+            rating,
+            rating_count,
             pages,
             posted,
+            uploader,
             cover,
         })
     }
 
-    /// 获取画廊的某一页的图片的 fileindex 和实际地址和 nl
+    /// 获取画廊的某一页的图片的 fileindex 和实际地址
     #[tracing::instrument(skip(self))]
     pub async fn get_image_url(&self, page: &EhPageUrl) -> Result<(u32, String)> {
-        let resp = send!(self.0.get(&page.url()))?;
-        let (url, nl, fileindex) = {
-            let html = Html::parse_document(&resp.text().await?);
-            let url = html.select_attr("img#img", "src").unwrap();
-            let nl = html.select_attr("img#img", "onerror").and_then(extract_nl);
-            let fileindex = extract_fileindex(&url).unwrap();
-            (url, nl, fileindex)
-        };
+        let (fileindex, url, nl) = self.get_image_page(page).await?;
 
         return if send!(self.0.head(&url)).is_ok() {
             Ok((fileindex, url))
-        } else if nl.is_some() {
-            let resp = send!(self.0.get(&page.with_nl(&nl.unwrap()).url()))?;
-            let html = Html::parse_document(&resp.text().await?);
-            let url = html.select_attr("img#img", "src").unwrap();
+        } else if let Some(nl) = nl {
+            let (_, url, _) = self.get_image_page(&page.with_nl(&nl)).await?;
             Ok((fileindex, url))
         } else {
             Err(EhError::HaHUrlBroken(url))
         };
     }
+
+    /// 通过 nl 参数重新获取图片地址，用于默认地址返回了错误内容的情况
+    #[tracing::instrument(skip(self))]
+    pub async fn get_image_url_fallback(&self, page: &EhPageUrl) -> Result<(u32, String)> {
+        let (fileindex, url, nl) = self.get_image_page(page).await?;
+        let nl = nl.ok_or(EhError::HaHUrlBroken(url))?;
+        let (_, url, _) = self.get_image_page(&page.with_nl(&nl)).await?;
+        Ok((fileindex, url))
+    }
+
+    /// 解析图片页面，返回图片的 fileindex、地址和 nl
+    async fn get_image_page(&self, page: &EhPageUrl) -> Result<(u32, String, Option<String>)> {
+        let resp = send!(self.0.get(page.url()))?;
+        let html = Html::parse_document(&resp.text().await?);
+        let url = html.select_attr("img#img", "src").unwrap();
+        let nl = html.select_attr("img#img", "onerror").and_then(extract_nl);
+        let fileindex = extract_fileindex(&url).unwrap();
+        Ok((fileindex, url, nl))
+    }
 }
 
 fn extract_fileindex(url: &str) -> Option<u32> {
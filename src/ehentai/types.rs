@@ -5,12 +5,13 @@ use chrono::prelude::*;
 use indexmap::IndexMap;
 use once_cell::sync::Lazy;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 use super::error::EhError;
 use crate::database::GalleryEntity;
 
 // 画廊地址，格式为 https://exhentai.org/g/2549143/16b1b7bab0/
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct EhGalleryUrl {
     id: i32,
     token: String,
@@ -65,7 +66,7 @@ impl Display for EhGalleryUrl {
 }
 
 /// 画廊页面地址，格式为 https://exhentai.org/s/03af734602/1932743-1
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct EhPageUrl {
     hash: String,
     gallery_id: i32,
@@ -135,7 +136,7 @@ impl Display for EhPageUrl {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EhGallery {
     /// URL
     pub url: EhGalleryUrl,
@@ -147,12 +148,20 @@ pub struct EhGallery {
     pub tags: IndexMap<String, Vec<String>>,
     /// 收藏数量
     pub favorite: i32,
+    /// 分类，如 Doujinshi、Manga
+    pub category: String,
+    /// 平均评分，0~5，尚未评分时为空
+    pub rating: Option<f32>,
+    /// 评分人数
+    pub rating_count: i32,
     /// 父画廊地址
     pub parent: Option<EhGalleryUrl>,
     /// 画廊页面
     pub pages: Vec<EhPageUrl>,
     /// 发布时间
     pub posted: NaiveDateTime,
+    /// 上传者，被删除的账号可能为空
+    pub uploader: Option<String>,
     /// 封面是第几张
     pub cover: usize,
 }
@@ -169,6 +178,12 @@ pub trait GalleryInfo {
     fn pages(&self) -> usize;
 
     fn cover(&self) -> usize;
+
+    fn parent(&self) -> Option<i32>;
+
+    fn uploader(&self) -> Option<String>;
+
+    fn posted(&self) -> Option<NaiveDateTime>;
 }
 
 impl GalleryInfo for EhGallery {
@@ -195,6 +210,18 @@ impl GalleryInfo for EhGallery {
     fn cover(&self) -> usize {
         self.cover
     }
+
+    fn parent(&self) -> Option<i32> {
+        self.parent.as_ref().map(|p| p.id())
+    }
+
+    fn uploader(&self) -> Option<String> {
+        self.uploader.clone()
+    }
+
+    fn posted(&self) -> Option<NaiveDateTime> {
+        Some(self.posted)
+    }
 }
 
 impl GalleryInfo for GalleryEntity {
@@ -221,6 +248,18 @@ impl GalleryInfo for GalleryEntity {
     fn cover(&self) -> usize {
         0
     }
+
+    fn parent(&self) -> Option<i32> {
+        self.parent
+    }
+
+    fn uploader(&self) -> Option<String> {
+        self.uploader.clone()
+    }
+
+    fn posted(&self) -> Option<NaiveDateTime> {
+        self.posted
+    }
 }
 
 #[cfg(test)]
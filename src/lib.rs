@@ -1,8 +1,12 @@
+mod article;
 pub mod bot;
 pub mod config;
 pub mod database;
 pub mod ehentai;
-mod s3;
+mod publisher;
+mod rules;
+pub mod storage;
 pub mod tags;
+mod template;
 pub mod uploader;
 pub mod utils;
@@ -0,0 +1,257 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Result;
+use indexmap::IndexMap;
+use telegraph_rs::Node;
+use tokio::sync::Mutex;
+
+use super::{PreviewPage, PreviewPublisher};
+use crate::config::HtmlPreview;
+use crate::storage::{Storage, StorageBackend};
+
+/// 上传至存储时，预览页面存放的目录
+const STORAGE_PREFIX: &str = "preview";
+
+/// 将预览渲染为静态 HTML 页面，并生成一个列出所有页面的索引
+#[derive(Debug, Clone)]
+pub struct HtmlPublisher {
+    output: Output,
+    /// 页面的公开访问地址前缀
+    host: String,
+    /// 保证索引的读写不会交错
+    lock: Arc<Mutex<()>>,
+}
+
+#[derive(Debug, Clone)]
+enum Output {
+    Dir(PathBuf),
+    Storage(StorageBackend),
+}
+
+impl HtmlPublisher {
+    pub fn new(config: &HtmlPreview, storage: &StorageBackend) -> Self {
+        if let Some(dir) = &config.dir {
+            return Self::with_dir(dir, &config.host);
+        }
+        Self {
+            output: Output::Storage(storage.clone()),
+            host: config.host.trim_end_matches('/').to_owned(),
+            lock: Default::default(),
+        }
+    }
+
+    /// 输出到本地目录
+    pub fn with_dir<P: AsRef<Path>>(dir: P, host: &str) -> Self {
+        Self {
+            output: Output::Dir(dir.as_ref().to_owned()),
+            host: host.trim_end_matches('/').to_owned(),
+            lock: Default::default(),
+        }
+    }
+
+    /// 写入页面，并更新索引
+    async fn write(&self, path: &str, title: &str, nodes: &[Node]) -> Result<PreviewPage> {
+        let name = format!("{}.html", path);
+        self.output.put(&name, render_page(title, &render_nodes(nodes)).as_bytes()).await?;
+
+        let _guard = self.lock.lock().await;
+        let mut index = match self.output.get("index.json").await? {
+            Some(data) => serde_json::from_slice::<IndexMap<String, String>>(&data)?,
+            None => IndexMap::new(),
+        };
+        // 新页面排在最前面
+        index.shift_remove(path);
+        index.shift_insert(0, path.to_owned(), title.to_owned());
+        self.output.put("index.json", &serde_json::to_vec(&index)?).await?;
+        self.output.put("index.html", render_index(&index).as_bytes()).await?;
+
+        Ok(PreviewPage {
+            path: path.to_owned(),
+            url: format!("{}/{}", self.host, name),
+            title: title.to_owned(),
+        })
+    }
+}
+
+impl PreviewPublisher for HtmlPublisher {
+    fn max_size(&self) -> usize {
+        // 静态页面没有大小限制，不需要拆分
+        usize::MAX
+    }
+
+    async fn create(&self, title: &str, nodes: &[Node]) -> Result<PreviewPage> {
+        let path = format!("{:016x}", rand::random::<u64>());
+        self.write(&path, title, nodes).await
+    }
+
+    async fn edit(&self, path: &str, title: &str, nodes: &[Node]) -> Result<PreviewPage> {
+        self.write(path, title, nodes).await
+    }
+
+    async fn check(&self, url: &str) -> Result<bool> {
+        let name = match url.strip_prefix(&self.host) {
+            Some(name) => name.trim_start_matches('/'),
+            None => return Ok(false),
+        };
+        match &self.output {
+            Output::Dir(dir) => Ok(dir.join(name).exists()),
+            Output::Storage(storage) => {
+                Ok(storage.head(&format!("{}/{}", STORAGE_PREFIX, name)).await?.is_some())
+            }
+        }
+    }
+}
+
+impl Output {
+    async fn put(&self, name: &str, data: &[u8]) -> Result<()> {
+        match self {
+            Self::Dir(dir) => {
+                std::fs::create_dir_all(dir)?;
+                std::fs::write(dir.join(name), data)?;
+            }
+            Self::Storage(storage) => {
+                storage.put(&format!("{}/{}", STORAGE_PREFIX, name), data).await?
+            }
+        }
+        Ok(())
+    }
+
+    async fn get(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        match self {
+            Self::Dir(dir) => match std::fs::read(dir.join(name)) {
+                Ok(data) => Ok(Some(data)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            },
+            Self::Storage(storage) => storage.get(&format!("{}/{}", STORAGE_PREFIX, name)).await,
+        }
+    }
+}
+
+/// 转义 HTML 文本与属性中的特殊字符
+fn escape(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            '\'' => ret.push_str("&#39;"),
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+fn render_nodes(nodes: &[Node]) -> String {
+    let mut html = String::new();
+    for node in nodes {
+        render_node(node, &mut html);
+    }
+    html
+}
+
+fn render_node(node: &Node, html: &mut String) {
+    let element = match node {
+        Node::Text(text) => return html.push_str(&escape(text)),
+        Node::NodeElement(element) => element,
+    };
+    html.push('<');
+    html.push_str(&element.tag);
+    for (key, value) in element.attrs.iter().flatten() {
+        html.push_str(&format!(r#" {}="{}""#, key, escape(value.as_deref().unwrap_or_default())));
+    }
+    if element.tag == "img" {
+        html.push_str(r#" loading="lazy""#);
+    }
+    html.push('>');
+    if matches!(element.tag.as_str(), "img" | "br" | "hr") {
+        return;
+    }
+    for child in element.children.iter().flatten() {
+        render_node(child, html);
+    }
+    html.push_str(&format!("</{}>", element.tag));
+}
+
+fn render_page(title: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="zh-CN">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<style>
+body {{ margin: 0; font-family: sans-serif; line-height: 1.6; background: #fff; color: #222; }}
+main {{ max-width: 800px; margin: 0 auto; padding: 1em; }}
+img {{ display: block; max-width: 100%; height: auto; margin: 0 auto 0.5em; }}
+a {{ color: #3b82f6; }}
+@media (prefers-color-scheme: dark) {{ body {{ background: #181818; color: #ddd; }} }}
+</style>
+</head>
+<body>
+<main>
+<h1>{title}</h1>
+{body}
+</main>
+</body>
+</html>
+"#,
+        title = escape(title),
+        body = body,
+    )
+}
+
+fn render_index(index: &IndexMap<String, String>) -> String {
+    let mut body = String::from("<ul>");
+    for (path, title) in index {
+        body.push_str(&format!(
+            r#"<li><a href="{}.html">{}</a></li>"#,
+            escape(path),
+            escape(title)
+        ));
+    }
+    body.push_str("</ul>");
+    render_page("预览列表", &body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::article;
+
+    #[tokio::test]
+    async fn publish_to_local_dir() {
+        let dir = std::env::temp_dir().join(format!("exloli-{:x}", rand::random::<u64>()));
+        let publisher = HtmlPublisher::with_dir(&dir, "https://example.com/preview/");
+        let nodes = vec![
+            article::p(vec![article::text("<script>alert(1)</script>")]),
+            article::img("https://example.com/a\".jpg"),
+        ];
+
+        let page = publisher.create("标题 & <b>", &nodes).await.unwrap();
+        assert_eq!(page.url, format!("https://example.com/preview/{}.html", page.path));
+        assert!(publisher.check(&page.url).await.unwrap());
+        assert!(!publisher.check("https://example.com/preview/404.html").await.unwrap());
+
+        let html = std::fs::read_to_string(dir.join(format!("{}.html", page.path))).unwrap();
+        assert!(html.contains("<title>标题 &amp; &lt;b&gt;</title>"));
+        assert!(html.contains("<p>&lt;script&gt;alert(1)&lt;/script&gt;</p>"));
+        assert!(html.contains(r#"<img src="https://example.com/a&quot;.jpg" loading="lazy">"#));
+
+        // 原地编辑后，地址不变，索引中只保留最新的标题
+        let other = publisher.create("另一篇", &nodes).await.unwrap();
+        let edited = publisher.edit(&page.path, "新标题", &nodes).await.unwrap();
+        assert_eq!(edited.url, page.url);
+        let index = std::fs::read_to_string(dir.join("index.html")).unwrap();
+        let first = index.find(&page.path).unwrap();
+        assert!(first < index.find(&other.path).unwrap());
+        assert!(index.contains("新标题"));
+        assert!(!index.contains("标题 &amp;"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
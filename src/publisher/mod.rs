@@ -0,0 +1,88 @@
+//! 预览页面的发布
+//!
+//! 默认发布到 telegraph，也可以渲染为静态 HTML 页面，以便在 telegraph 无法访问时继续提供预览
+
+mod html;
+mod telegraph;
+
+use anyhow::Result;
+use telegraph_rs::Node;
+
+pub use self::html::HtmlPublisher;
+pub use self::telegraph::{is_page_not_found, TelegraphPublisher};
+use crate::config::{Config, Preview};
+use crate::storage::StorageBackend;
+
+/// 一篇已发布的预览页面
+#[derive(Debug, Clone)]
+pub struct PreviewPage {
+    /// 页面路径，用于之后原地编辑
+    pub path: String,
+    /// 页面的公开访问地址
+    pub url: String,
+    /// 页面标题
+    pub title: String,
+}
+
+/// 预览页面的发布方式
+pub trait PreviewPublisher {
+    /// 单篇页面的内容上限，超出后需要拆分为多篇
+    fn max_size(&self) -> usize;
+
+    /// 发布一篇新的页面
+    async fn create(&self, title: &str, nodes: &[Node]) -> Result<PreviewPage>;
+
+    /// 原地编辑已有的页面
+    async fn edit(&self, path: &str, title: &str, nodes: &[Node]) -> Result<PreviewPage>;
+
+    /// 检查页面是否仍然可以访问
+    async fn check(&self, url: &str) -> Result<bool>;
+}
+
+/// 根据配置文件选择的发布方式
+#[derive(Debug, Clone)]
+pub enum Publisher {
+    Telegraph(TelegraphPublisher),
+    Html(HtmlPublisher),
+}
+
+impl Publisher {
+    pub async fn new(config: &Config, storage: &StorageBackend) -> Result<Self> {
+        Ok(match &config.preview {
+            Preview::Telegraph => {
+                Self::Telegraph(TelegraphPublisher::new(&config.telegraph).await?)
+            }
+            Preview::Html(html) => Self::Html(HtmlPublisher::new(html, storage)),
+        })
+    }
+}
+
+impl PreviewPublisher for Publisher {
+    fn max_size(&self) -> usize {
+        match self {
+            Self::Telegraph(p) => p.max_size(),
+            Self::Html(p) => p.max_size(),
+        }
+    }
+
+    async fn create(&self, title: &str, nodes: &[Node]) -> Result<PreviewPage> {
+        match self {
+            Self::Telegraph(p) => p.create(title, nodes).await,
+            Self::Html(p) => p.create(title, nodes).await,
+        }
+    }
+
+    async fn edit(&self, path: &str, title: &str, nodes: &[Node]) -> Result<PreviewPage> {
+        match self {
+            Self::Telegraph(p) => p.edit(path, title, nodes).await,
+            Self::Html(p) => p.edit(path, title, nodes).await,
+        }
+    }
+
+    async fn check(&self, url: &str) -> Result<bool> {
+        match self {
+            Self::Telegraph(p) => p.check(url).await,
+            Self::Html(p) => p.check(url).await,
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use telegraph_rs::{Node, Page, Telegraph};
+use tracing::{info, warn};
+
+use super::{PreviewPage, PreviewPublisher};
+use crate::config;
+use crate::database::TelegraphAccountEntity;
+
+/// telegraph 单篇文章的内容上限为 64KB，此处留出一些余量给导航链接
+const MAX_ARTICLE_SIZE: usize = 60 * 1024;
+
+/// 使用账号池轮流发布文章，连续失败过多的账号会被停用，并自动创建新账号补充
+#[derive(Debug, Clone)]
+pub struct TelegraphPublisher {
+    config: config::Telegraph,
+    /// 配置文件中的账号，用于编辑没有记录所属账号的旧文章
+    default: i64,
+    /// 下一次发布使用的账号序号
+    next: Arc<AtomicUsize>,
+}
+
+impl TelegraphPublisher {
+    pub async fn new(config: &config::Telegraph) -> Result<Self> {
+        let default = TelegraphAccountEntity::create(&config.access_token).await?.id;
+        let publisher = Self { config: config.clone(), default, next: Default::default() };
+        publisher.fill_pool().await?;
+        Ok(publisher)
+    }
+
+    /// 列出账号池中的所有账号，以及各自的文章数量
+    pub async fn accounts(&self) -> Result<Vec<(TelegraphAccountEntity, Option<i32>)>> {
+        let mut ret = vec![];
+        for account in TelegraphAccountEntity::list().await? {
+            let info = self.client(&account).await?.get_account_info(&["page_count"]).await;
+            let page_count = match info {
+                Ok(info) => info.page_count,
+                Err(err) => {
+                    warn!("获取 telegraph 账号信息失败：{} {}", account.id, err);
+                    None
+                }
+            };
+            ret.push((account, page_count));
+        }
+        Ok(ret)
+    }
+
+    /// 可用的账号不足时，创建新的账号，返回所有可用的账号
+    ///
+    /// 创建账号失败时（如被限流或 telegraph 故障）继续使用已有的账号，只有一个可用账号都没有时才返回错误
+    async fn fill_pool(&self) -> Result<Vec<TelegraphAccountEntity>> {
+        let mut accounts = TelegraphAccountEntity::list_active().await?;
+        while accounts.len() < self.config.pool_size.unwrap_or(1).max(1) {
+            let token = match self.create_account().await {
+                Ok(token) => token,
+                Err(err) if !accounts.is_empty() => {
+                    warn!(
+                        "创建 telegraph 账号失败，继续使用已有的 {} 个账号：{}",
+                        accounts.len(),
+                        err
+                    );
+                    break;
+                }
+                Err(err) => return Err(err.context("没有可用的 telegraph 账号")),
+            };
+            let account = TelegraphAccountEntity::create(&token).await?;
+            info!("创建 telegraph 账号：{}", account.id);
+            accounts.push(account);
+        }
+        Ok(accounts)
+    }
+
+    /// 调用 API 创建一个新账号，返回其 token
+    async fn create_account(&self) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Account {
+            access_token: String,
+        }
+        #[derive(Deserialize)]
+        struct Response {
+            result: Option<Account>,
+            error: Option<String>,
+        }
+
+        let resp = Client::new()
+            .post("https://api.telegra.ph/createAccount")
+            .form(&[
+                ("short_name", self.config.author_name.as_str()),
+                ("author_name", self.config.author_name.as_str()),
+                ("author_url", self.config.author_url.as_str()),
+            ])
+            .send()
+            .await?
+            .json::<Response>()
+            .await?;
+        match resp.result {
+            Some(account) => Ok(account.access_token),
+            None => Err(anyhow!("创建 telegraph 账号失败：{}", resp.error.unwrap_or_default())),
+        }
+    }
+
+    async fn client(&self, account: &TelegraphAccountEntity) -> Result<Telegraph> {
+        // NOTE: 设置了 access_token 时，此处不会发送请求
+        Ok(Telegraph::new(&self.config.author_name)
+            .author_url(&self.config.author_url)
+            .access_token(&account.access_token)
+            .create()
+            .await?)
+    }
+}
+
+impl PreviewPublisher for TelegraphPublisher {
+    fn max_size(&self) -> usize {
+        MAX_ARTICLE_SIZE
+    }
+
+    async fn create(&self, title: &str, nodes: &[Node]) -> Result<PreviewPage> {
+        let content = serde_json::to_string(nodes)?;
+        let accounts = self.fill_pool().await?;
+        let max_failures = self.config.max_failures.unwrap_or(3);
+        let mut error = None;
+        // 依次尝试每个账号，直到发布成功
+        for _ in 0..accounts.len() {
+            let account = &accounts[self.next.fetch_add(1, Ordering::Relaxed) % accounts.len()];
+            match self.client(account).await?.create_page(title, &content, false).await {
+                Ok(page) => {
+                    TelegraphAccountEntity::record_success(account.id).await?;
+                    TelegraphAccountEntity::add_page(account.id, &page.path).await?;
+                    return Ok(page.into());
+                }
+                Err(err) => match classify(&err) {
+                    // 内容过大、网络错误、telegraph 故障等与账号无关，换账号也没用，直接返回
+                    Failure::Other => return Err(err.into()),
+                    // 限流是暂时的，本次跳过该账号即可，不计入失败次数
+                    Failure::RateLimited => {
+                        warn!("telegraph 账号 {} 被限流：{}", account.id, err);
+                        error = Some(err);
+                    }
+                    Failure::Account => {
+                        warn!("telegraph 账号 {} 发布失败：{}", account.id, err);
+                        if TelegraphAccountEntity::record_failure(account.id).await? >= max_failures
+                        {
+                            warn!("停用 telegraph 账号：{}", account.id);
+                            TelegraphAccountEntity::retire(account.id).await?;
+                        }
+                        error = Some(err);
+                    }
+                },
+            }
+        }
+        Err(error.map(Into::into).unwrap_or_else(|| anyhow!("没有可用的 telegraph 账号")))
+    }
+
+    async fn edit(&self, path: &str, title: &str, nodes: &[Node]) -> Result<PreviewPage> {
+        let content = serde_json::to_string(nodes)?;
+        // 文章只能由创建它的账号编辑，没有记录的旧文章都是由配置文件中的账号创建的
+        let account = match TelegraphAccountEntity::get_by_page(path).await? {
+            Some(account) => account,
+            None => TelegraphAccountEntity::get(self.default)
+                .await?
+                .ok_or_else(|| anyhow!("找不到 telegraph 账号"))?,
+        };
+        Ok(self.client(&account).await?.edit_page(path, title, &content, false).await?.into())
+    }
+
+    async fn check(&self, url: &str) -> Result<bool> {
+        Ok(Client::new().head(url).send().await?.status() != StatusCode::NOT_FOUND)
+    }
+}
+
+/// 发布失败的原因，决定是否换账号重试以及是否计入账号的失败次数
+#[derive(Debug, PartialEq, Eq)]
+enum Failure {
+    /// 与账号无关的错误
+    Other,
+    /// 账号被暂时限流
+    RateLimited,
+    /// 账号本身的问题，例如 token 失效
+    Account,
+}
+
+fn classify(err: &telegraph_rs::Error) -> Failure {
+    const ACCOUNT_ERRORS: [&str; 2] = ["ACCESS_TOKEN_INVALID", "ACCOUNT_"];
+    match err {
+        telegraph_rs::Error::ApiError(e) if e.starts_with("FLOOD_WAIT") => Failure::RateLimited,
+        telegraph_rs::Error::ApiError(e) if ACCOUNT_ERRORS.iter().any(|p| e.starts_with(p)) => {
+            Failure::Account
+        }
+        _ => Failure::Other,
+    }
+}
+
+/// 编辑文章失败是否是因为文章已经不存在
+pub fn is_page_not_found(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref(), Some(telegraph_rs::Error::ApiError(e)) if e == "PAGE_NOT_FOUND")
+}
+
+impl From<Page> for PreviewPage {
+    fn from(page: Page) -> Self {
+        Self { path: page.path, url: page.url, title: page.title }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_account_errors_count_as_failures() {
+        let api = |e: &str| telegraph_rs::Error::ApiError(e.to_owned());
+        assert_eq!(classify(&api("FLOOD_WAIT_5")), Failure::RateLimited);
+        assert_eq!(classify(&api("ACCESS_TOKEN_INVALID")), Failure::Account);
+        assert_eq!(classify(&api("CONTENT_TOO_BIG")), Failure::Other);
+        let io = std::io::Error::new(std::io::ErrorKind::TimedOut, "timeout");
+        assert_eq!(classify(&telegraph_rs::Error::IoError(io)), Failure::Other);
+    }
+}
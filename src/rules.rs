@@ -0,0 +1,215 @@
+//! 上传规则
+//!
+//! 按顺序匹配配置中的规则，第一条所有条件都满足的规则决定画廊的处理方式，没有规则命中时使用默认处理方式
+
+use std::fmt::Display;
+
+use chrono::NaiveDateTime;
+use indexmap::IndexMap;
+
+use crate::config::{Filter, Rule, RuleAction};
+use crate::ehentai::EhGallery;
+
+/// 规则的匹配结果
+#[derive(Debug, Clone)]
+pub struct Evaluation {
+    /// 最终的处理方式
+    pub action: RuleAction,
+    /// 命中的规则名称，没有规则命中时为空
+    pub rule: Option<String>,
+    /// 依次匹配的规则，以及未命中的原因
+    pub trace: Vec<(String, Option<String>)>,
+}
+
+impl Display for Evaluation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (name, reason) in &self.trace {
+            match reason {
+                Some(reason) => writeln!(f, "✗ {}：{}", name, reason)?,
+                None => writeln!(f, "✓ {}", name)?,
+            }
+        }
+        match &self.rule {
+            Some(rule) => write!(f, "结果：{}（{}）", self.action, rule),
+            None => write!(f, "结果：{}（没有规则命中）", self.action),
+        }
+    }
+}
+
+/// 使用配置中的规则检查画廊，now 用于计算画廊的发布时长
+pub fn evaluate(filter: &Filter, gallery: &EhGallery, now: NaiveDateTime) -> Evaluation {
+    let mut trace = vec![];
+    for rule in &filter.rules {
+        let reason = check(rule, gallery, now).err();
+        let matched = reason.is_none();
+        trace.push((rule.name.clone(), reason));
+        if matched {
+            return Evaluation {
+                action: rule.action.clone(),
+                rule: Some(rule.name.clone()),
+                trace,
+            };
+        }
+    }
+    Evaluation { action: filter.default.clone(), rule: None, trace }
+}
+
+/// 检查画廊是否满足规则的所有条件，不满足时返回第一个不满足的条件
+fn check(rule: &Rule, gallery: &EhGallery, now: NaiveDateTime) -> Result<(), String> {
+    if !rule.include.is_empty() && !rule.include.iter().any(|p| has_tag(&gallery.tags, p)) {
+        return Err(format!("不包含以下任一标签：{}", rule.include.join(", ")));
+    }
+    if let Some(tag) = rule.exclude.iter().find(|p| has_tag(&gallery.tags, p)) {
+        return Err(format!("包含标签 {}", tag));
+    }
+    if let Some(range) = &rule.pages {
+        if !range.contains(gallery.pages.len()) {
+            return Err(format!("页数 {} 不在 {} 范围内", gallery.pages.len(), range));
+        }
+    }
+    if let Some(range) = &rule.favorites {
+        if !range.contains(gallery.favorite) {
+            return Err(format!("收藏数 {} 不在 {} 范围内", gallery.favorite, range));
+        }
+    }
+    if let Some(range) = &rule.rating {
+        match gallery.rating {
+            Some(rating) if range.contains(rating) => {}
+            Some(rating) => return Err(format!("评分 {} 不在 {} 范围内", rating, range)),
+            None => return Err("尚未评分".to_owned()),
+        }
+    }
+    if !rule.category.is_empty()
+        && !rule.category.iter().any(|c| c.eq_ignore_ascii_case(&gallery.category))
+    {
+        return Err(format!("分类 {} 不在 {} 中", gallery.category, rule.category.join(", ")));
+    }
+    if !rule.language.is_empty() {
+        let languages = languages(&gallery.tags);
+        if !rule.language.iter().any(|l| languages.iter().any(|g| l.eq_ignore_ascii_case(g))) {
+            return Err(format!(
+                "语言 {} 不在 {} 中",
+                languages.join(", "),
+                rule.language.join(", ")
+            ));
+        }
+    }
+    if !rule.uploader.is_empty() {
+        let uploader = gallery.uploader.as_deref().unwrap_or_default();
+        if !rule.uploader.iter().any(|u| u == uploader) {
+            return Err(format!("上传者 {} 不在 {} 中", uploader, rule.uploader.join(", ")));
+        }
+    }
+    if let Some(range) = &rule.age {
+        let age = (now - gallery.posted).num_hours();
+        if !range.contains(age) {
+            return Err(format!("发布时长 {} 小时不在 {} 范围内", age, range));
+        }
+    }
+    Ok(())
+}
+
+/// 检查画廊是否包含匹配的标签，格式为 namespace:tag，namespace 或 tag 为 * 时匹配任意值
+fn has_tag(tags: &IndexMap<String, Vec<String>>, pattern: &str) -> bool {
+    let (namespace, tag) = pattern.split_once(':').unwrap_or(("*", pattern));
+    tags.iter()
+        .filter(|(ns, _)| namespace == "*" || namespace.eq_ignore_ascii_case(ns))
+        .flat_map(|(_, tags)| tags)
+        .any(|t| tag == "*" || tag.eq_ignore_ascii_case(t))
+}
+
+/// 画廊的语言，不包括 translated 和 rewrite，没有语言标签时视为日语
+fn languages(tags: &IndexMap<String, Vec<String>>) -> Vec<&str> {
+    let languages = tags
+        .get("language")
+        .into_iter()
+        .flatten()
+        .map(|s| s.as_str())
+        .filter(|s| !matches!(*s, "translated" | "rewrite"))
+        .collect::<Vec<_>>();
+    if languages.is_empty() {
+        vec!["japanese"]
+    } else {
+        languages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Range;
+
+    fn gallery() -> EhGallery {
+        let mut tags = IndexMap::new();
+        tags.insert("language".to_owned(), vec!["chinese".to_owned(), "translated".to_owned()]);
+        tags.insert("female".to_owned(), vec!["lolicon".to_owned(), "twintails".to_owned()]);
+        EhGallery {
+            url: "https://exhentai.org/g/2423705/3962191348/".parse().unwrap(),
+            title: "title".to_owned(),
+            title_jp: None,
+            tags,
+            favorite: 120,
+            category: "Doujinshi".to_owned(),
+            rating: Some(4.5),
+            rating_count: 30,
+            parent: None,
+            pages: vec![],
+            posted: NaiveDateTime::parse_from_str("2024-01-01 00:00", "%Y-%m-%d %H:%M").unwrap(),
+            uploader: Some("someone".to_owned()),
+            cover: 0,
+        }
+    }
+
+    fn rule(name: &str, action: RuleAction) -> Rule {
+        Rule {
+            name: name.to_owned(),
+            action,
+            include: vec![],
+            exclude: vec![],
+            pages: None,
+            favorites: None,
+            rating: None,
+            category: vec![],
+            language: vec![],
+            uploader: vec![],
+            age: None,
+        }
+    }
+
+    #[test]
+    fn tag_patterns() {
+        let tags = gallery().tags;
+        assert!(has_tag(&tags, "female:lolicon"));
+        assert!(has_tag(&tags, "*:twintails"));
+        assert!(has_tag(&tags, "twintails"));
+        assert!(has_tag(&tags, "female:*"));
+        assert!(!has_tag(&tags, "male:*"));
+        assert!(!has_tag(&tags, "male:lolicon"));
+        assert_eq!(languages(&tags), vec!["chinese"]);
+        assert_eq!(languages(&IndexMap::new()), vec!["japanese"]);
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let now = NaiveDateTime::parse_from_str("2024-01-02 00:00", "%Y-%m-%d %H:%M").unwrap();
+        let mut skip = rule("skip", RuleAction::Skip);
+        skip.exclude = vec!["female:*".to_owned()];
+        let mut hold = rule("hold", RuleAction::Hold);
+        hold.language = vec!["Chinese".to_owned()];
+        hold.rating = Some(Range { min: Some(4.0), max: None });
+        hold.age = Some(Range { min: None, max: Some(24) });
+        let upload = rule("upload", RuleAction::Upload);
+        let filter =
+            Filter { default: RuleAction::Skip, rules: vec![skip, hold, upload], expire: None };
+
+        let eval = evaluate(&filter, &gallery(), now);
+        assert_eq!(eval.action, RuleAction::Hold);
+        assert_eq!(eval.rule.as_deref(), Some("hold"));
+        assert_eq!(eval.trace.len(), 2);
+        assert_eq!(eval.trace[0].1.as_deref(), Some("包含标签 female:*"));
+
+        let eval = evaluate(&Filter { rules: vec![], ..filter }, &gallery(), now);
+        assert_eq!(eval.action, RuleAction::Skip);
+        assert_eq!(eval.rule, None);
+    }
+}
@@ -0,0 +1,132 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use super::{Object, Storage};
+use crate::config::{self, PublicHosts};
+
+/// 保存到本地目录，对象键即为相对路径
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    dir: PathBuf,
+    hosts: PublicHosts,
+}
+
+impl LocalStorage {
+    pub fn new(config: &config::LocalStorage) -> Self {
+        Self { dir: config.dir.clone(), hosts: config.hosts.clone() }
+    }
+}
+
+impl Storage for LocalStorage {
+    fn id(&self) -> &'static str {
+        "local"
+    }
+
+    fn public_urls(&self, key: &str) -> Vec<String> {
+        self.hosts.urls(key)
+    }
+
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        let path = self.dir.join(key);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match std::fs::read(self.dir.join(key)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn head(&self, key: &str) -> Result<Option<Object>> {
+        match std::fs::metadata(self.dir.join(key)) {
+            Ok(meta) if meta.is_file() => {
+                Ok(Some(Object { key: key.to_owned(), size: meta.len(), etag: None }))
+            }
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match std::fs::remove_file(self.dir.join(key)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<Object>> {
+        let mut objects = vec![];
+        if self.dir.exists() {
+            walk(&self.dir, "", prefix, &mut objects)?;
+        }
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+}
+
+/// 递归列出目录中的文件，跳过不可能以 prefix 开头的子目录
+fn walk(dir: &Path, base: &str, prefix: &str, objects: &mut Vec<Object>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let key = format!("{}{}", base, entry.file_name().to_string_lossy());
+        let meta = entry.metadata()?;
+        if meta.is_dir() {
+            let base = format!("{}/", key);
+            if base.starts_with(prefix) || prefix.starts_with(&base) {
+                walk(&entry.path(), &base, prefix, objects)?;
+            }
+        } else if key.starts_with(prefix) {
+            objects.push(Object { key, size: meta.len(), etag: None });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn local_storage() {
+        let dir = std::env::temp_dir().join(format!("exloli-{:x}", rand::random::<u64>()));
+        let hosts = PublicHosts {
+            host: "http://localhost:8080/files/".to_owned(),
+            mirrors: vec!["cdn.example.com".to_owned()],
+        };
+        let storage = LocalStorage::new(&config::LocalStorage { dir: dir.clone(), hosts });
+
+        assert_eq!(storage.list("").await.unwrap(), vec![]);
+        storage.put("abcdef0123.jpg", b"image").await.unwrap();
+        storage.put("grid/1.jpg", b"grid").await.unwrap();
+        storage.put("grid/2.jpg", b"grid 2").await.unwrap();
+
+        assert_eq!(storage.get("grid/1.jpg").await.unwrap().as_deref(), Some(&b"grid"[..]));
+        assert_eq!(storage.get("404.jpg").await.unwrap(), None);
+        assert_eq!(storage.head("abcdef0123.jpg").await.unwrap().unwrap().size, 5);
+        assert_eq!(storage.head("grid").await.unwrap(), None);
+        let keys = |objects: Vec<Object>| objects.into_iter().map(|o| o.key).collect::<Vec<_>>();
+        assert_eq!(keys(storage.list("grid/").await.unwrap()), vec!["grid/1.jpg", "grid/2.jpg"]);
+        assert_eq!(keys(storage.list("gr").await.unwrap()), vec!["grid/1.jpg", "grid/2.jpg"]);
+        assert_eq!(storage.list("").await.unwrap().len(), 3);
+
+        storage.delete("grid/1.jpg").await.unwrap();
+        storage.delete("grid/1.jpg").await.unwrap();
+        assert_eq!(keys(storage.list("grid/").await.unwrap()), vec!["grid/2.jpg"]);
+
+        assert_eq!(
+            storage.public_urls("grid/2.jpg"),
+            vec!["http://localhost:8080/files/grid/2.jpg", "https://cdn.example.com/grid/2.jpg"]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,241 @@
+//! 图片等文件的存储
+//!
+//! 默认上传至 s3，也可以保存到本地目录交给静态文件服务器提供访问，或者上传至 WebDAV
+
+mod local;
+mod s3;
+mod webdav;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+
+pub use self::local::LocalStorage;
+pub use self::s3::S3Storage;
+pub use self::webdav::WebdavStorage;
+use crate::config::{self, Config, PublicHosts};
+use crate::database::STORAGE_TELEGRAPH;
+
+/// 存储中的一个文件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Object {
+    /// 对象键
+    pub key: String,
+    /// 文件大小
+    pub size: u64,
+    /// ETag，后端不支持时为空
+    pub etag: Option<String>,
+}
+
+/// 文件的存储方式
+///
+/// 只通过 StorageBackend 静态分发，不需要约束返回的 Future 为 Send
+#[allow(async_fn_in_trait)]
+pub trait Storage {
+    /// 后端 ID，会记录在图片的 storage 字段中
+    fn id(&self) -> &'static str;
+
+    /// 文件的公开访问地址，主地址在前，镜像地址在后
+    fn public_urls(&self, key: &str) -> Vec<String>;
+
+    /// 文件的主公开访问地址
+    fn public_url(&self, key: &str) -> String {
+        self.public_urls(key).swap_remove(0)
+    }
+
+    /// 上传文件，已存在时覆盖
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()>;
+
+    /// 读取文件内容，文件不存在时返回 None
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// 获取文件的大小和 ETag，文件不存在时返回 None
+    async fn head(&self, key: &str) -> Result<Option<Object>>;
+
+    /// 删除文件，文件不存在时不视为错误
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// 列出对象键以 prefix 开头的所有文件
+    async fn list(&self, prefix: &str) -> Result<Vec<Object>>;
+}
+
+/// 根据配置文件选择的存储方式
+#[derive(Debug, Clone)]
+pub struct StorageBackend {
+    /// 当前用于上传的存储
+    backend: Backend,
+    /// 各存储后端的公开访问地址，按后端 ID 索引，切换存储方式后之前上传的文件仍然使用原来的地址
+    hosts: Arc<HashMap<&'static str, PublicHosts>>,
+}
+
+#[derive(Debug, Clone)]
+enum Backend {
+    S3(S3Storage),
+    Local(LocalStorage),
+    Webdav(WebdavStorage),
+}
+
+impl StorageBackend {
+    pub fn new(config: &Config) -> Result<Self> {
+        let backend = match &config.storage {
+            config::Storage::S3 => {
+                let s3 = config.s3.as_ref().ok_or(anyhow!("使用 s3 存储时必须设置 [s3]"))?;
+                Backend::S3(S3Storage::new(s3)?)
+            }
+            config::Storage::Local(local) => Backend::Local(LocalStorage::new(local)),
+            config::Storage::Webdav(webdav) => Backend::Webdav(WebdavStorage::new(webdav)?),
+        };
+        // 保留 [s3] 时，之前上传到 s3 的文件仍然可以通过其中的地址访问
+        let mut hosts = HashMap::new();
+        if let Some(s3) = &config.s3 {
+            hosts.insert("s3", s3.hosts.clone());
+        }
+        match &config.storage {
+            config::Storage::S3 => {}
+            config::Storage::Local(local) => _ = hosts.insert("local", local.hosts.clone()),
+            config::Storage::Webdav(webdav) => _ = hosts.insert("webdav", webdav.hosts.clone()),
+        }
+        Ok(Self { backend, hosts: Arc::new(hosts) })
+    }
+
+    /// 文件的公开访问地址，根据记录中的后端 ID 生成，主地址在前，镜像地址在后
+    ///
+    /// 早期的图片存放在 telegraph 上，迁移完成前需要保留这个特例，返回 telegraph 上的完整地址；
+    /// 配置中已经找不到对应后端的文件，只能认为已经被复制到了当前的存储中
+    pub fn image_urls(&self, storage: &str, key: &str) -> Vec<String> {
+        match storage {
+            STORAGE_TELEGRAPH => vec![format!("https://telegra.ph/{}", key)],
+            id => match self.hosts.get(id) {
+                Some(hosts) => hosts.urls(key),
+                None => self.public_urls(key),
+            },
+        }
+    }
+
+    /// 根据配置中各后端的地址，从旧数据记录的完整地址中拆分出后端 ID 和对象键，认不出地址时返回 None
+    pub fn split_url(&self, url: &str) -> Option<(&'static str, String)> {
+        self.hosts.iter().find_map(|(id, hosts)| {
+            hosts.urls("").iter().find_map(|prefix| {
+                let key = url.strip_prefix(prefix.as_str())?;
+                (!key.is_empty()).then(|| (*id, key.to_owned()))
+            })
+        })
+    }
+}
+
+impl Storage for StorageBackend {
+    fn id(&self) -> &'static str {
+        match &self.backend {
+            Backend::S3(s) => s.id(),
+            Backend::Local(s) => s.id(),
+            Backend::Webdav(s) => s.id(),
+        }
+    }
+
+    fn public_urls(&self, key: &str) -> Vec<String> {
+        match &self.backend {
+            Backend::S3(s) => s.public_urls(key),
+            Backend::Local(s) => s.public_urls(key),
+            Backend::Webdav(s) => s.public_urls(key),
+        }
+    }
+
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        match &self.backend {
+            Backend::S3(s) => s.put(key, data).await,
+            Backend::Local(s) => s.put(key, data).await,
+            Backend::Webdav(s) => s.put(key, data).await,
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match &self.backend {
+            Backend::S3(s) => s.get(key).await,
+            Backend::Local(s) => s.get(key).await,
+            Backend::Webdav(s) => s.get(key).await,
+        }
+    }
+
+    async fn head(&self, key: &str) -> Result<Option<Object>> {
+        match &self.backend {
+            Backend::S3(s) => s.head(key).await,
+            Backend::Local(s) => s.head(key).await,
+            Backend::Webdav(s) => s.head(key).await,
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match &self.backend {
+            Backend::S3(s) => s.delete(key).await,
+            Backend::Local(s) => s.delete(key).await,
+            Backend::Webdav(s) => s.delete(key).await,
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<Object>> {
+        match &self.backend {
+            Backend::S3(s) => s.list(prefix).await,
+            Backend::Local(s) => s.list(prefix).await,
+            Backend::Webdav(s) => s.list(prefix).await,
+        }
+    }
+}
+
+fn content_type(key: &str) -> &'static str {
+    if key.ends_with(".jpg") {
+        "image/jpeg"
+    } else if key.ends_with(".png") {
+        "image/png"
+    } else if key.ends_with(".webp") {
+        "image/webp"
+    } else if key.ends_with(".html") {
+        "text/html; charset=utf-8"
+    } else if key.ends_with(".json") {
+        "application/json"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_urls_follow_stored_backend() {
+        let hosts = |host: &str| PublicHosts { host: host.to_owned(), mirrors: vec![] };
+        let local = config::LocalStorage { dir: std::env::temp_dir(), hosts: hosts("local.test") };
+        let storage = StorageBackend {
+            backend: Backend::Local(LocalStorage::new(&local)),
+            hosts: Arc::new(HashMap::from([
+                ("s3", hosts("s3.test/exloli")),
+                ("local", hosts("local.test")),
+            ])),
+        };
+        assert_eq!(
+            storage.image_urls(STORAGE_TELEGRAPH, "file/abc.jpg"),
+            vec!["https://telegra.ph/file/abc.jpg"]
+        );
+        assert_eq!(storage.image_urls("s3", "abc.jpg"), vec!["https://s3.test/exloli/abc.jpg"]);
+        assert_eq!(storage.image_urls("local", "abc.jpg"), vec!["https://local.test/abc.jpg"]);
+        assert_eq!(storage.image_urls("webdav", "abc.jpg"), vec!["https://local.test/abc.jpg"]);
+
+        // 地址中带有路径时，路径属于地址而不是对象键
+        assert_eq!(
+            storage.split_url("https://s3.test/exloli/abc.jpg"),
+            Some(("s3", "abc.jpg".to_owned()))
+        );
+        assert_eq!(storage.split_url("https://other.test/exloli/abc.jpg"), None);
+    }
+
+    #[test]
+    fn content_type_by_extension() {
+        assert_eq!(content_type("abc.jpg"), "image/jpeg");
+        assert_eq!(content_type("sheet/abc.webp"), "image/webp");
+        assert_eq!(content_type("abc.json"), "application/json");
+        // 未知的扩展名不应该导致上传时 panic
+        assert_eq!(content_type("abc.gif"), "application/octet-stream");
+        assert_eq!(content_type("abc"), "application/octet-stream");
+    }
+}
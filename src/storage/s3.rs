@@ -0,0 +1,74 @@
+use anyhow::Result;
+use s3::creds::Credentials;
+use s3::error::S3Error;
+use s3::{Bucket, Region};
+
+use super::{content_type, Object, Storage};
+use crate::config::{PublicHosts, S3};
+
+#[derive(Debug, Clone)]
+pub struct S3Storage {
+    bucket: Box<Bucket>,
+    hosts: PublicHosts,
+}
+
+impl S3Storage {
+    pub fn new(s3: &S3) -> Result<Self, S3Error> {
+        let region = Region::Custom { region: s3.region.clone(), endpoint: s3.endpoint.clone() };
+        let credentials =
+            Credentials::new(Some(&s3.access_key), Some(&s3.secret_key), None, None, None)?;
+        let bucket = Bucket::new(&s3.bucket, region, credentials)?;
+        Ok(Self { bucket, hosts: s3.hosts.clone() })
+    }
+}
+
+impl Storage for S3Storage {
+    fn id(&self) -> &'static str {
+        "s3"
+    }
+
+    fn public_urls(&self, key: &str) -> Vec<String> {
+        self.hosts.urls(key)
+    }
+
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        self.bucket.put_object_with_content_type(key, data, content_type(key)).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match self.bucket.get_object(key).await {
+            Ok(resp) => Ok(Some(resp.to_vec())),
+            Err(S3Error::HttpFailWithBody(404, _)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn head(&self, key: &str) -> Result<Option<Object>> {
+        match self.bucket.head_object(key).await {
+            Ok((head, _)) => Ok(Some(Object {
+                key: key.to_owned(),
+                size: head.content_length.unwrap_or_default() as u64,
+                etag: head.e_tag,
+            })),
+            Err(S3Error::HttpFailWithBody(404, _)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match self.bucket.delete_object(key).await {
+            Ok(_) | Err(S3Error::HttpFailWithBody(404, _)) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<Object>> {
+        let pages = self.bucket.list(prefix.to_owned(), None).await?;
+        Ok(pages
+            .into_iter()
+            .flat_map(|page| page.contents)
+            .map(|obj| Object { key: obj.key, size: obj.size, etag: obj.e_tag })
+            .collect())
+    }
+}
@@ -0,0 +1,200 @@
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use dashmap::DashSet;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG};
+use reqwest::{Client, Method, RequestBuilder, StatusCode, Url};
+
+use super::{content_type, Object, Storage};
+use crate::config::{self, PublicHosts};
+
+static RESPONSE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?s)<(?:\w+:)?response\b.*?</(?:\w+:)?response>").unwrap());
+static HREF: Lazy<Regex> = Lazy::new(|| prop("href"));
+static LENGTH: Lazy<Regex> = Lazy::new(|| prop("getcontentlength"));
+static ETAG_PROP: Lazy<Regex> = Lazy::new(|| prop("getetag"));
+static COLLECTION: Lazy<Regex> = Lazy::new(|| Regex::new(r"<(?:\w+:)?collection\b").unwrap());
+
+const PROPFIND: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:"><d:prop><d:resourcetype/><d:getcontentlength/><d:getetag/></d:prop></d:propfind>"#;
+
+fn prop(name: &str) -> Regex {
+    Regex::new(&format!(r"(?s)<(?:\w+:)?{0}\b[^>]*>(.*?)</(?:\w+:)?{0}>", name)).unwrap()
+}
+
+/// 上传至 WebDAV，对象键即为相对于配置中地址的路径
+#[derive(Debug, Clone)]
+pub struct WebdavStorage {
+    client: Client,
+    url: Url,
+    username: Option<String>,
+    password: Option<String>,
+    hosts: PublicHosts,
+    /// 已经确认存在的目录，避免每次上传都重复创建
+    dirs: Arc<DashSet<String>>,
+}
+
+impl WebdavStorage {
+    pub fn new(config: &config::WebdavStorage) -> Result<Self> {
+        // 以 / 结尾才能正确拼接相对路径
+        let url = Url::parse(&format!("{}/", config.url.trim_end_matches('/')))?;
+        Ok(Self {
+            client: Client::new(),
+            url,
+            username: config.username.clone(),
+            password: config.password.clone(),
+            hosts: config.hosts.clone(),
+            dirs: Default::default(),
+        })
+    }
+
+    fn request(&self, method: Method, key: &str) -> Result<RequestBuilder> {
+        let req = self.client.request(method, self.url.join(key)?);
+        Ok(match &self.username {
+            Some(username) => req.basic_auth(username, self.password.as_ref()),
+            None => req,
+        })
+    }
+
+    /// 依次创建对象键中的各级目录
+    async fn create_dirs(&self, key: &str) -> Result<()> {
+        let parts = key.split('/').collect::<Vec<_>>();
+        let mut dir = String::new();
+        for part in &parts[..parts.len() - 1] {
+            dir.push_str(part);
+            dir.push('/');
+            if self.dirs.contains(&dir) {
+                continue;
+            }
+            let resp = self.request(Method::from_bytes(b"MKCOL")?, &dir)?.send().await?;
+            // 目录已存在时返回 405
+            if !resp.status().is_success() && resp.status() != StatusCode::METHOD_NOT_ALLOWED {
+                bail!("创建目录失败：{} {}", dir, resp.status());
+            }
+            self.dirs.insert(dir.clone());
+        }
+        Ok(())
+    }
+
+    /// 列出目录下的文件和子目录，子目录的对象键以 / 结尾
+    async fn propfind(&self, dir: &str) -> Result<Vec<Object>> {
+        let resp = self
+            .request(Method::from_bytes(b"PROPFIND")?, dir)?
+            .header("Depth", "1")
+            .header(CONTENT_TYPE, "application/xml")
+            .body(PROPFIND)
+            .send()
+            .await?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(vec![]);
+        }
+        let body = resp.error_for_status()?.text().await?;
+        let mut objects = vec![];
+        for block in RESPONSE.find_iter(&body) {
+            let block = block.as_str();
+            let href = match HREF.captures(block) {
+                Some(cap) => cap[1].trim().to_owned(),
+                None => continue,
+            };
+            // href 可能是完整的地址，也可能只有路径
+            let path = match self.url.join(&href) {
+                Ok(url) => url.path().to_owned(),
+                Err(_) => continue,
+            };
+            let key = match path.strip_prefix(self.url.path()) {
+                // 结果中包含目录本身，需要跳过
+                Some(key) if key.trim_end_matches('/') != dir.trim_end_matches('/') => {
+                    key.to_owned()
+                }
+                _ => continue,
+            };
+            let is_dir = COLLECTION.is_match(block);
+            objects.push(Object {
+                key: match is_dir {
+                    true => format!("{}/", key.trim_end_matches('/')),
+                    false => key,
+                },
+                size: LENGTH.captures(block).and_then(|c| c[1].trim().parse().ok()).unwrap_or(0),
+                etag: ETAG_PROP.captures(block).map(|c| c[1].trim().replace("&quot;", "\"")),
+            });
+        }
+        Ok(objects)
+    }
+}
+
+impl Storage for WebdavStorage {
+    fn id(&self) -> &'static str {
+        "webdav"
+    }
+
+    fn public_urls(&self, key: &str) -> Vec<String> {
+        self.hosts.urls(key)
+    }
+
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        self.create_dirs(key).await?;
+        self.request(Method::PUT, key)?
+            .header(CONTENT_TYPE, content_type(key))
+            .body(data.to_vec())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let resp = self.request(Method::GET, key)?.send().await?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(resp.error_for_status()?.bytes().await?.to_vec()))
+    }
+
+    async fn head(&self, key: &str) -> Result<Option<Object>> {
+        let resp = self.request(Method::HEAD, key)?.send().await?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let resp = resp.error_for_status()?;
+        // HEAD 请求没有响应体，需要直接读取 Content-Length
+        let header = |name| resp.headers().get(name).and_then(|v| v.to_str().ok());
+        Ok(Some(Object {
+            key: key.to_owned(),
+            size: header(CONTENT_LENGTH).and_then(|v| v.parse().ok()).unwrap_or(0),
+            etag: header(ETAG).map(str::to_owned),
+        }))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let resp = self.request(Method::DELETE, key)?.send().await?;
+        if resp.status() != StatusCode::NOT_FOUND {
+            resp.error_for_status()?;
+        }
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<Object>> {
+        // 从 prefix 所在的目录开始，逐层列出可能以 prefix 开头的子目录
+        let start = match prefix.rfind('/') {
+            Some(i) => &prefix[..=i],
+            None => "",
+        };
+        let mut dirs = vec![start.to_owned()];
+        let mut objects = vec![];
+        while let Some(dir) = dirs.pop() {
+            for obj in self.propfind(&dir).await? {
+                if obj.key.ends_with('/') {
+                    if obj.key.starts_with(prefix) || prefix.starts_with(&obj.key) {
+                        dirs.push(obj.key);
+                    }
+                } else if obj.key.starts_with(prefix) {
+                    objects.push(obj);
+                }
+            }
+        }
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+}
@@ -0,0 +1,329 @@
+//! 频道消息的模板
+//!
+//! 模板使用 jinja2 语法，输出为 telegram 的 HTML 格式，所有变量都会被自动转义
+
+use anyhow::{bail, Result};
+use minijinja::{Environment, Value};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use teloxide::utils::html::{code_inline, escape, link};
+
+use crate::utils::{html_text_len, pad_left};
+
+/// 默认模板，与最初硬编码的消息格式保持一致
+pub const DEFAULT_TEMPLATE: &str = r#"{% for group in tags -%}
+{{ group.namespace | pad(6) | code }}: {{ group.tags | map("hashtag") | join(" ") }}{% if group.more %} +{{ group.more }} more{% endif %}
+{% endfor -%}
+{% if note -%}
+{{ "  附言" | code }}: {{ note }}
+{% endif -%}
+{{ "  预览" | code }}: {{ link(article, title) }}
+{{ "原始地址" | code }}: {{ url }}"#;
+
+/// 渲染模板时可以使用的变量
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageContext {
+    /// 画廊 ID
+    pub id: i32,
+    /// 画廊地址
+    pub url: String,
+    /// 英文标题
+    pub title: String,
+    /// 日文标题，没有日文标题时与英文标题相同
+    pub title_jp: String,
+    /// 预览文章地址
+    pub article: String,
+    /// 页数
+    pub pages: usize,
+    /// 上传者
+    pub uploader: Option<String>,
+    /// 发布时间
+    pub posted: Option<String>,
+    /// 评分，0~100，尚未投票时为空
+    pub score: Option<f32>,
+    /// 父画廊的链接，优先使用频道消息
+    pub parent: Option<String>,
+    /// 管理员审核时填写的附言
+    pub note: Option<String>,
+    /// 按 namespace 分组的标签
+    pub tags: Vec<TagGroup>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TagGroup {
+    /// 翻译后的 namespace
+    pub namespace: String,
+    /// 原始 namespace
+    pub raw_namespace: String,
+    /// 翻译后的标签
+    pub tags: Vec<String>,
+    /// 原始标签
+    pub raw_tags: Vec<String>,
+    /// 因消息过长而被省略的标签数量
+    pub more: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct MessageTemplate {
+    env: Environment<'static>,
+}
+
+impl MessageTemplate {
+    /// 编译模板，不传入则使用默认模板
+    pub fn new(source: Option<&str>) -> Result<Self> {
+        let mut env = Environment::new();
+        // NOTE: minijinja 自带的 HTML 转义会转义 /，导致链接难以阅读，因此这里使用 telegram 的转义规则
+        env.set_formatter(|out, _, value| {
+            if value.is_safe() {
+                write!(out, "{}", value)?;
+            } else if !value.is_none() && !value.is_undefined() {
+                write!(out, "{}", escape(&value.to_string()))?;
+            }
+            Ok(())
+        });
+        // 左侧填充空格到指定宽度，用于对齐
+        env.add_filter("pad", |s: String, width: usize| pad_left(&s, width).into_owned());
+        // 转换为 telegram 中可以点击的 hashtag
+        env.add_filter("hashtag", |s: String| {
+            static RE: Lazy<Regex> = Lazy::new(|| Regex::new("[-/· ]").unwrap());
+            format!("#{}", RE.replace_all(&s, "_"))
+        });
+        env.add_filter("code", |s: String| Value::from_safe_string(code_inline(&s)));
+        env.add_function("link", |url: String, text: String| {
+            Value::from_safe_string(link(&url, &text))
+        });
+        env.add_template_owned("message", source.unwrap_or(DEFAULT_TEMPLATE).to_owned())?;
+        Ok(Self { env })
+    }
+
+    pub fn render(&self, ctx: &MessageContext) -> Result<String> {
+        Ok(self.env.get_template("message")?.render(ctx)?)
+    }
+
+    /// 渲染模板，并保证解析后的长度不超过 limit
+    ///
+    /// 超出时按以下顺序删减，每一步后重新渲染，直到长度符合要求：
+    /// 1. 按 low_priority 的顺序删除整个 namespace
+    /// 2. 限制每个 namespace 显示的标签数量，优先省略标签最多的 namespace，每个 namespace 至少保留一个
+    /// 3. 从后往前删除整个 namespace
+    /// 4. 截断标题
+    ///
+    /// 第 2、3 步使用二分查找，避免标签过多时反复渲染
+    pub fn render_fit(
+        &self,
+        ctx: &MessageContext,
+        limit: usize,
+        low_priority: &[String],
+    ) -> Result<String> {
+        let mut ctx = ctx.clone();
+        let text = self.render(&ctx)?;
+        if html_text_len(&text) <= limit {
+            return Ok(text);
+        }
+
+        for ns in low_priority {
+            if !ctx.tags.iter().any(|g| &g.raw_namespace == ns) {
+                continue;
+            }
+            ctx.tags.retain(|g| &g.raw_namespace != ns);
+            let text = self.render(&ctx)?;
+            if html_text_len(&text) <= limit {
+                return Ok(text);
+            }
+        }
+
+        let max = ctx.tags.iter().map(|g| g.tags.len()).max().unwrap_or(0);
+        let collapsed = |cap: usize| {
+            let mut ctx = ctx.clone();
+            for group in &mut ctx.tags {
+                if group.tags.len() > cap {
+                    group.more += group.tags.len() - cap;
+                    group.tags.truncate(cap);
+                    group.raw_tags.truncate(cap);
+                }
+            }
+            ctx
+        };
+        if let Some(text) = self.render_max(0, max, limit, collapsed)? {
+            return Ok(text);
+        }
+        ctx = collapsed(1);
+
+        let truncated = |count: usize| {
+            let mut ctx = ctx.clone();
+            ctx.tags.truncate(count);
+            ctx
+        };
+        if let Some(text) = self.render_max(0, ctx.tags.len(), limit, truncated)? {
+            return Ok(text);
+        }
+        ctx.tags.clear();
+
+        // 此时只剩下标题可以删减，按超出的长度截断
+        let excess = html_text_len(&self.render(&ctx)?) - limit;
+        for title in [&mut ctx.title, &mut ctx.title_jp] {
+            let keep = title.chars().count().saturating_sub(excess + 1);
+            *title = format!("{}…", title.chars().take(keep).collect::<String>());
+        }
+        let text = self.render(&ctx)?;
+        let len = html_text_len(&text);
+        if len > limit {
+            bail!("消息长度 {} 超过上限 {}，请检查模板", len, limit);
+        }
+        Ok(text)
+    }
+
+    /// 在 (low, high] 中找到最大的 n，使得 f(n) 渲染出的消息不超过 limit，返回渲染结果
+    ///
+    /// 要求消息长度随 n 单调递增，f(low) 视为不满足
+    fn render_max(
+        &self,
+        mut low: usize,
+        mut high: usize,
+        limit: usize,
+        f: impl Fn(usize) -> MessageContext,
+    ) -> Result<Option<String>> {
+        let mut found = None;
+        while low < high {
+            let mid = low + (high - low).div_ceil(2);
+            let text = self.render(&f(mid))?;
+            if html_text_len(&text) <= limit {
+                found = Some(text);
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(ns: &str, tags: Vec<String>) -> TagGroup {
+        TagGroup {
+            namespace: ns.into(),
+            raw_namespace: ns.into(),
+            tags: tags.clone(),
+            raw_tags: tags,
+            more: 0,
+        }
+    }
+
+    fn context(tags: Vec<TagGroup>) -> MessageContext {
+        MessageContext {
+            id: 1,
+            url: "https://exhentai.org/g/1/abc/".into(),
+            title: "<Title> & Co".into(),
+            title_jp: "タイトル".into(),
+            article: "https://telegra.ph/abc".into(),
+            pages: 10,
+            uploader: None,
+            posted: None,
+            score: None,
+            parent: None,
+            note: None,
+            tags,
+        }
+    }
+
+    fn numbered(prefix: &str, count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("{prefix} {i}")).collect()
+    }
+
+    /// 预览和原始地址两行必须保留
+    fn assert_links(text: &str) {
+        assert!(text.contains("<code>  预览</code>: <a href=\"https://telegra.ph/abc\">"));
+        assert!(text.ends_with("<code>原始地址</code>: https://exhentai.org/g/1/abc/"));
+    }
+
+    #[test]
+    fn default_template() {
+        let ctx = context(vec![TagGroup {
+            namespace: "女性".into(),
+            raw_namespace: "female".into(),
+            tags: vec!["萝莉".into(), "big breasts".into()],
+            raw_tags: vec!["lolicon".into(), "big breasts".into()],
+            more: 0,
+        }]);
+        let text = MessageTemplate::new(None).unwrap().render(&ctx).unwrap();
+        assert_eq!(
+            text,
+            "<code>  女性</code>: #萝莉 #big_breasts\n\
+             <code>  预览</code>: <a href=\"https://telegra.ph/abc\">&lt;Title&gt; &amp; Co</a>\n\
+             <code>原始地址</code>: https://exhentai.org/g/1/abc/"
+        );
+    }
+
+    #[test]
+    fn fit_short_message_unchanged() {
+        let template = MessageTemplate::new(None).unwrap();
+        let ctx = context(vec![group("female", numbered("tag", 5))]);
+        let text = template.render_fit(&ctx, 4096, &[]).unwrap();
+        assert_eq!(text, template.render(&ctx).unwrap());
+    }
+
+    #[test]
+    fn fit_drops_low_priority_first() {
+        let template = MessageTemplate::new(None).unwrap();
+        let ctx = context(vec![
+            group("language", numbered("language", 300)),
+            group("female", numbered("female", 20)),
+        ]);
+        let low = vec!["language".to_owned()];
+        let text = template.render_fit(&ctx, 4096, &low).unwrap();
+        assert!(html_text_len(&text) <= 4096);
+        assert!(!text.contains("#language"));
+        assert!(text.contains("#female_19"));
+        assert!(!text.contains("more"));
+        assert_links(&text);
+    }
+
+    #[test]
+    fn fit_collapses_longest_namespace() {
+        let template = MessageTemplate::new(None).unwrap();
+        let ctx = context(vec![
+            group("female", numbered("female", 1000)),
+            group("male", numbered("male", 3)),
+        ]);
+        let text = template.render_fit(&ctx, 4096, &[]).unwrap();
+        assert!(html_text_len(&text) <= 4096);
+        assert!(text.contains("#female_0"));
+        assert!(text.contains(" more\n"));
+        assert!(text.contains("#male_0 #male_1 #male_2\n"));
+        assert_links(&text);
+    }
+
+    #[test]
+    fn fit_caption_with_many_namespaces() {
+        let template = MessageTemplate::new(None).unwrap();
+        let tags = (0..200).map(|i| group(&format!("ns{i}"), numbered("tag", 50))).collect();
+        let text = template.render_fit(&context(tags), 1024, &[]).unwrap();
+        assert!(html_text_len(&text) <= 1024);
+        assert!(text.contains("ns0"));
+        assert!(!text.contains("ns199"));
+        assert_links(&text);
+    }
+
+    #[test]
+    fn fit_truncates_title() {
+        let template = MessageTemplate::new(None).unwrap();
+        let mut ctx = context(vec![group("female", numbered("female", 10))]);
+        ctx.title = "&".repeat(5000);
+        let text = template.render_fit(&ctx, 4096, &[]).unwrap();
+        assert!(html_text_len(&text) <= 4096);
+        assert!(!text.contains("#female"));
+        assert!(text.contains("&amp;…</a>"));
+        assert_links(&text);
+    }
+
+    #[test]
+    fn fit_fails_on_oversized_template() {
+        let source = format!("{}{{{{ url }}}}", "x".repeat(5000));
+        let template = MessageTemplate::new(Some(&source)).unwrap();
+        assert!(template.render_fit(&context(vec![]), 4096, &[]).is_err());
+    }
+}
@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use reqwest::Client;
+use tokio::time;
+use tracing::{error, info, warn};
+
+use super::images::download_stored;
+use super::ExloliUploader;
+use crate::config::ArticleHeader;
+use crate::database::{ContactSheetEntity, ImageEntity, ImageFlag};
+use crate::storage::Storage;
+use crate::utils::contact_sheet;
+
+impl ExloliUploader {
+    /// 为画廊生成缩略图并上传，缩略图由均匀抽取的若干页拼接而成，会跳过被标记为广告或无效的图片
+    pub async fn create_contact_sheet(&self, gallery_id: i32) -> Result<()> {
+        let images = ImageEntity::get_by_gallery_id(gallery_id)
+            .await?
+            .into_iter()
+            .filter(|img| img.flag == ImageFlag::Ok)
+            .collect::<Vec<_>>();
+        let count = (contact_sheet::COLUMNS * contact_sheet::ROWS) as usize;
+        let client = Client::builder().timeout(Duration::from_secs(30)).build()?;
+        let mut thumbs = vec![];
+        for i in contact_sheet::sample_evenly(images.len(), count) {
+            let rst = async {
+                let bytes = download_stored(&client, &self.storage, &images[i]).await?;
+                Result::<_>::Ok(image::load_from_memory(&bytes)?)
+            };
+            match rst.await {
+                Ok(image) => thumbs.push(image),
+                Err(err) => warn!("下载图片失败：{} {}", images[i].key, err),
+            }
+        }
+        if thumbs.is_empty() {
+            bail!("没有可用的图片");
+        }
+
+        let data = contact_sheet::contact_sheet(&thumbs)?;
+        let name = format!("grid/{}.jpg", gallery_id);
+        self.storage.put(&name, &data).await?;
+        ContactSheetEntity::create(gallery_id, self.storage.id(), &name).await?;
+        Ok(())
+    }
+
+    /// 为已发布但还没有缩略图的画廊补充缩略图，并更新其文章
+    pub async fn backfill_contact_sheets(&self) -> Result<()> {
+        for gallery_id in ContactSheetEntity::list_missing().await? {
+            info!("生成缩略图：{}", gallery_id);
+            if let Err(err) = self.create_contact_sheet(gallery_id).await {
+                error!("生成缩略图失败：{} {}", gallery_id, err);
+                continue;
+            }
+            if self.config.telegraph.header.contains(&ArticleHeader::Grid) {
+                if let Err(err) = self.refresh_article(gallery_id).await {
+                    error!("更新文章失败：{} {}", gallery_id, err);
+                }
+            }
+            time::sleep(Duration::from_secs(1)).await;
+        }
+        Ok(())
+    }
+}
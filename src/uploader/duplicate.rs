@@ -0,0 +1,127 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use teloxide::prelude::*;
+use teloxide::utils::html::link;
+
+use super::ExloliUploader;
+use crate::bot::duplicate_keyboard;
+use crate::config::Duplicate;
+use crate::database::{GalleryEntity, ImageEntity, ImageFlag, MessageEntity, PageEntity};
+use crate::ehentai::{EhGallery, GalleryInfo};
+
+impl ExloliUploader {
+    /// 根据页面的哈希和感知哈希，查找与指定画廊重合度最高的已发布画廊，返回画廊 ID 和重合度
+    ///
+    /// 父画廊不会被视为重复
+    pub(super) async fn find_duplicate(
+        &self,
+        gallery: &EhGallery,
+        cfg: &Duplicate,
+    ) -> Result<Option<(i32, f32)>> {
+        // 广告图片会出现在很多画廊中，不能参与比较
+        let images = ImageEntity::get_by_gallery_id(gallery.url.id())
+            .await?
+            .into_iter()
+            .filter(|img| img.flag == ImageFlag::Ok)
+            .collect::<Vec<_>>();
+        if images.is_empty() {
+            return Ok(None);
+        }
+
+        // 找出每一页出现在哪些画廊中
+        let mut pages = vec![];
+        for image in &images {
+            let mut similar = vec![image.id];
+            if let Some(phash) = image.phash {
+                let result = ImageEntity::search_by_phash(phash, cfg.distance).await?;
+                similar.extend(
+                    result
+                        .into_iter()
+                        .filter(|(img, _)| img.flag == ImageFlag::Ok)
+                        .map(|(img, _)| img.id),
+                );
+            }
+            let mut related = HashSet::new();
+            for id in similar {
+                related
+                    .extend(PageEntity::get_by_image(id).await?.into_iter().map(|p| p.gallery_id));
+            }
+            related.remove(&gallery.url.id());
+            if let Some(parent) = &gallery.parent {
+                related.remove(&parent.id());
+            }
+            pages.push(related);
+        }
+
+        // 只考虑已经发布过的画廊
+        for (id, overlap) in rank_overlaps(&pages, cfg.threshold) {
+            if MessageEntity::get_by_gallery(id).await?.is_some() {
+                return Ok(Some((id, overlap)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// 在群组中询问管理员如何处理疑似重复的画廊
+    pub(super) async fn ask_duplicate(
+        &self,
+        gallery: &EhGallery,
+        related: i32,
+        overlap: f32,
+    ) -> Result<()> {
+        let related = GalleryEntity::get(related).await?.ok_or(anyhow!("找不到画廊"))?;
+        let text = format!(
+            "新画廊 {} 与已发布的画廊 {} 的页面重合度为 {:.0}%，请选择处理方式",
+            link(&gallery.url.url(), &gallery.title_jp()),
+            link(&related.url().url(), &related.title_jp()),
+            overlap * 100.
+        );
+        self.bot
+            .send_message(self.config.telegram.main.group_id, text)
+            .reply_markup(duplicate_keyboard(gallery.url.id(), related.id))
+            .disable_web_page_preview(true)
+            .await?;
+        Ok(())
+    }
+}
+
+/// 根据每一页出现在哪些画廊中，统计各画廊与当前画廊的重合度
+///
+/// 只返回重合度不低于 threshold 的画廊，按重合度从高到低排列，重合度相同时较早的画廊在前
+fn rank_overlaps(pages: &[HashSet<i32>], threshold: f32) -> Vec<(i32, f32)> {
+    let mut counter = HashMap::<i32, usize>::new();
+    for id in pages.iter().flatten() {
+        *counter.entry(*id).or_default() += 1;
+    }
+    let mut counter = counter.into_iter().collect::<Vec<_>>();
+    counter.sort_by_key(|(id, count)| (std::cmp::Reverse(*count), *id));
+    counter
+        .into_iter()
+        .map(|(id, count)| (id, count as f32 / pages.len() as f32))
+        .take_while(|(_, overlap)| *overlap >= threshold)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlap_ratio() {
+        let pages = [
+            HashSet::from([1, 2]),
+            HashSet::from([1, 3]),
+            HashSet::from([1, 2]),
+            HashSet::from([]),
+        ];
+        assert_eq!(rank_overlaps(&pages, 0.), vec![(1, 0.75), (2, 0.5), (3, 0.25)]);
+        assert_eq!(rank_overlaps(&pages, 0.5), vec![(1, 0.75), (2, 0.5)]);
+        assert_eq!(rank_overlaps(&pages, 0.8), vec![]);
+
+        // 重合度相同时较早的画廊在前
+        let pages = [HashSet::from([5, 4]), HashSet::from([4, 5])];
+        assert_eq!(rank_overlaps(&pages, 0.6), vec![(4, 1.), (5, 1.)]);
+        assert_eq!(rank_overlaps(&[], 0.6), vec![]);
+    }
+}
@@ -0,0 +1,415 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use reqwest::Client;
+use sha1::{Digest, Sha1};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn, Instrument};
+
+use super::ExloliUploader;
+use crate::database::{
+    GalleryEntity, GalleryRelationEntity, ImageEntity, ImageFlag, PageEntity, RelationKind,
+};
+use crate::ehentai::{EhGallery, EhPageUrl};
+use crate::storage::{Storage, StorageBackend};
+use crate::utils::has_qrcode;
+use crate::utils::imagehash::{dhash, same_picture};
+
+impl ExloliUploader {
+    /// 标记图片，并更新所有使用了该图片的画廊的文章
+    pub async fn flag_image(&self, image_id: u32, flag: ImageFlag) -> Result<()> {
+        ImageEntity::update_flag(image_id, flag).await?;
+        // 同一张图片可能出现在多个画廊中，某个画廊更新失败时不影响其他画廊
+        let galleries = PageEntity::get_by_image(image_id)
+            .await?
+            .into_iter()
+            .map(|p| p.gallery_id)
+            .collect::<HashSet<_>>();
+        for gallery_id in galleries {
+            if let Err(err) = self.refresh_article(gallery_id).await {
+                error!("更新文章失败：{} {}", gallery_id, err);
+            }
+        }
+        Ok(())
+    }
+
+    /// 获取某个画廊里的所有图片，并且上传到 telegrpah，如果已经上传过的，会跳过上传
+    pub(super) async fn upload_gallery_image(&self, gallery: &EhGallery) -> Result<()> {
+        // 扫描所有图片
+        // 对于已经上传过的图片，不需要重复上传，只需要插入 PageEntity 记录即可
+        let mut pages = vec![];
+        for page in &gallery.pages {
+            // 页面 hash 只是 sha1 的前 10 位，存在碰撞的可能，因此只对没有记录完整 sha1 的旧图片直接复用，
+            // 其他图片需要下载后按完整 sha1 判断是否重复
+            match ImageEntity::get_by_hash(page.hash()).await? {
+                Some(img) if img.sha1.is_none() => {
+                    // NOTE: 此处存在重复插入的可能，但是由于 PageEntity::create 使用 OR IGNORE，所以不影响
+                    PageEntity::create(page.gallery_id(), page.page(), img.id).await?;
+                }
+                _ => pages.push(page.clone()),
+            }
+        }
+        info!("需要下载&上传 {} 张图片", pages.len());
+
+        let concurrent = self.config.threads_num;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(concurrent * 2);
+        let client = self.ehentai.clone();
+
+        // 获取图片链接时不要并行，避免触发反爬限制
+        let getter = tokio::spawn(
+            async move {
+                for page in pages {
+                    let rst = client.get_image_url(&page).await?;
+                    info!("已解析：{}", page.page());
+                    tx.send((page, rst)).await?;
+                }
+                Result::<()>::Ok(())
+            }
+            .in_current_span(),
+        );
+
+        // 依次将图片下载并上传到 r2，并插入 ImageEntity 和 PageEntity 记录
+        let storage = self.storage.clone();
+        let phash_threshold = self.config.phash_threshold;
+        let ehentai = self.ehentai.clone();
+        let client = Client::builder()
+            .timeout(Duration::from_secs(30))
+            .connect_timeout(Duration::from_secs(30))
+            .build()?;
+        let uploader = tokio::spawn(
+            async move {
+                while let Some((page, (fileindex, url))) = rx.recv().await {
+                    if url.ends_with(".gif") {
+                        continue;
+                    }
+                    let fallback = async { Ok(ehentai.get_image_url_fallback(&page).await?.1) };
+                    let (url, bytes, sha1) = download_image(&client, &page, url, fallback).await?;
+                    debug!("已下载: {}", page.page());
+                    // 内容完全相同的图片已经上传过了，直接复用
+                    if let Some(img) = ImageEntity::get_by_sha1(&sha1).await? {
+                        PageEntity::create(page.gallery_id(), page.page(), img.id).await?;
+                        continue;
+                    }
+                    let phash = dhash(&bytes).map_err(|e| warn!("计算感知哈希失败：{}", e)).ok();
+                    // 包含二维码的图片基本都是广告，只记录不上传
+                    if has_qrcode(&bytes).unwrap_or_default() {
+                        info!("跳过包含二维码的图片: {}", page.page());
+                        ImageEntity::create(
+                            fileindex,
+                            page.hash(),
+                            Some(&sha1),
+                            phash,
+                            ImageFlag::Ad,
+                            storage.id(),
+                            "",
+                        )
+                        .await?;
+                        PageEntity::create(page.gallery_id(), page.page(), fileindex).await?;
+                        continue;
+                    }
+                    // 如果存在足够相似的图片，则复用其文件和标记，不再重复上传
+                    let candidates = match (phash, phash_threshold) {
+                        (Some(phash), Some(d)) => ImageEntity::search_by_phash(phash, d).await?,
+                        _ => vec![],
+                    };
+                    let similar = find_same_picture(&client, &storage, &bytes, candidates).await;
+                    let (file_sha1, storage, key, flag, size) = match similar {
+                        Some((img, d)) => {
+                            debug!("复用相似图片: {} -> {}（距离 {}）", page.page(), img.id, d);
+                            // 广告图片没有上传文件，沿用标记即可
+                            let file_sha1 = match img.flag {
+                                ImageFlag::Ad => Some(sha1.clone()),
+                                _ => img.sha1,
+                            };
+                            (file_sha1, img.storage, img.key, img.flag, img.size)
+                        }
+                        None => {
+                            let suffix = url.rsplit('.').next().unwrap_or("jpg");
+                            let filename = format!("{}.{}", page.hash(), suffix);
+                            storage.put(&filename, &bytes).await?;
+                            debug!("已上传: {}", page.page());
+                            let size = Some(bytes.len() as i64);
+                            let id = storage.id().to_string();
+                            (Some(sha1.clone()), id, filename, ImageFlag::Ok, size)
+                        }
+                    };
+                    ImageEntity::create(
+                        fileindex,
+                        page.hash(),
+                        file_sha1.as_deref(),
+                        phash,
+                        flag,
+                        &storage,
+                        &key,
+                    )
+                    .await?;
+                    if let Some(size) = size {
+                        ImageEntity::update_integrity(fileindex, size, None).await?;
+                    }
+                    PageEntity::create(page.gallery_id(), page.page(), fileindex).await?;
+                }
+                Result::<()>::Ok(())
+            }
+            .in_current_span(),
+        );
+
+        tokio::try_join!(flatten(getter), flatten(uploader))?;
+
+        if let Err(err) = self.flag_common_images(gallery).await {
+            error!("标记广告图片失败：{}", err);
+        }
+        Ok(())
+    }
+
+    /// 将画廊中出现在足够多个不相关画廊中的图片标记为广告
+    ///
+    /// 画廊的更新版本和被标记为另一个版本的画廊会复用原画廊的图片，因此同一条版本链只计一次；
+    /// 只检查刚上传的画廊，避免每次扫描都对整张表做统计
+    async fn flag_common_images(&self, gallery: &EhGallery) -> Result<()> {
+        let count = match self.config.ad_gallery_count {
+            Some(count) => count as usize,
+            None => return Ok(()),
+        };
+        let mut roots = HashMap::new();
+        // 当前画廊可能还没有写入数据库，直接从父画廊开始查找
+        let root = match &gallery.parent {
+            Some(parent) => version_root(parent.id(), &mut roots).await?,
+            None => gallery.url.id(),
+        };
+        roots.insert(gallery.url.id(), root);
+
+        let mut checked = HashSet::new();
+        for image in ImageEntity::get_by_gallery_id(gallery.url.id()).await? {
+            if image.flag != ImageFlag::Ok || !checked.insert(image.id) {
+                continue;
+            }
+            let pages = PageEntity::get_by_image(image.id).await?;
+            if pages.len() < count {
+                continue;
+            }
+            let mut chains = HashSet::new();
+            for page in pages {
+                chains.insert(version_root(page.gallery_id, &mut roots).await?);
+            }
+            if chains.len() >= count {
+                info!("标记广告图片：{}（出现在 {} 个画廊中）", image.id, chains.len());
+                ImageEntity::update_flag(image.id, ImageFlag::Ad).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 为没有感知哈希的旧图片补充感知哈希
+    pub async fn rehash(&self) -> Result<()> {
+        let client = Client::builder().timeout(Duration::from_secs(30)).build()?;
+        let mut after = 0;
+        loop {
+            let images = ImageEntity::list_without_phash(after, 100).await?;
+            match images.last() {
+                Some(img) => after = img.id,
+                None => break,
+            }
+            for img in images {
+                let rst = async {
+                    let bytes = download_stored(&client, &self.storage, &img).await?;
+                    let phash = dhash(&bytes)?;
+                    ImageEntity::update_phash(img.id, phash).await?;
+                    Result::<()>::Ok(())
+                };
+                if let Err(err) = rst.await {
+                    error!("计算感知哈希失败：{} {}", img.key, err);
+                }
+            }
+            info!("已处理到图片：{}", after);
+        }
+        Ok(())
+    }
+}
+
+/// 沿着父画廊和“另一个版本”关系向上查找，返回版本链中最早的画廊 ID
+async fn version_root(gallery_id: i32, cache: &mut HashMap<i32, i32>) -> Result<i32> {
+    if let Some(root) = cache.get(&gallery_id) {
+        return Ok(*root);
+    }
+    let mut id = gallery_id;
+    // 限制查找深度，避免数据异常时出现环
+    for _ in 0..32 {
+        let parent = match GalleryEntity::get(id).await?.and_then(|g| g.parent) {
+            Some(parent) => Some(parent),
+            None => GalleryRelationEntity::get(id)
+                .await?
+                .filter(|r| r.kind == RelationKind::Version)
+                .map(|r| r.related_id),
+        };
+        match parent {
+            Some(parent) => id = parent,
+            None => break,
+        }
+    }
+    cache.insert(gallery_id, id);
+    Ok(id)
+}
+
+/// 从感知哈希相近的候选图片中，找出与 bytes 确实是同一张图的图片
+///
+/// 广告图片没有上传文件，只要感知哈希相近就视为同一张图
+async fn find_same_picture(
+    client: &Client,
+    storage: &StorageBackend,
+    bytes: &[u8],
+    candidates: Vec<(ImageEntity, u32)>,
+) -> Option<(ImageEntity, u32)> {
+    // 只比较最相近的几张，避免下载过多文件
+    for (img, d) in candidates.into_iter().take(3) {
+        if img.flag == ImageFlag::Ad {
+            return Some((img, d));
+        }
+        let rst = async {
+            let other = download_stored(client, storage, &img).await?;
+            same_picture(bytes, &other)
+        };
+        match rst.await {
+            Ok(true) => return Some((img, d)),
+            Ok(false) => debug!("相似图片内容不同，不复用：{}", img.id),
+            Err(err) => warn!("比较相似图片失败：{} {}", img.id, err),
+        }
+    }
+    None
+}
+
+/// 下载图片并校验其 sha1 是否与页面 hash 一致，返回实际地址、图片内容和完整的 sha1
+///
+/// 校验失败时（比如响应被截断、返回了错误页面或 509 图片），会通过 fallback（即 nl）重新获取地址并再试一次
+pub(super) async fn download_image(
+    client: &Client,
+    page: &EhPageUrl,
+    url: String,
+    fallback: impl Future<Output = Result<String>>,
+) -> Result<(String, Vec<u8>, String)> {
+    match download_verified(client, page, &url).await {
+        Ok((bytes, sha1)) => return Ok((url, bytes, sha1)),
+        Err(err) => warn!("图片下载失败，尝试重新获取：{} {} {}", page, url, err),
+    }
+    let url = fallback.await?;
+    let (bytes, sha1) = download_verified(client, page, &url).await?;
+    Ok((url, bytes, sha1))
+}
+
+/// 下载图片并校验其 sha1 是否与页面哈希一致，返回图片内容和完整的 sha1
+async fn download_verified(
+    client: &Client,
+    page: &EhPageUrl,
+    url: &str,
+) -> Result<(Vec<u8>, String)> {
+    let bytes = client.get(url).send().await?.error_for_status()?.bytes().await?.to_vec();
+    let sha1 = format!("{:x}", Sha1::digest(&bytes));
+    if !sha1.starts_with(page.hash()) {
+        bail!("图片校验失败：{}", page);
+    }
+    Ok((bytes, sha1))
+}
+
+/// 下载存储中的图片，主地址失败时依次尝试镜像地址
+pub(super) async fn download_stored(
+    client: &Client,
+    storage: &StorageBackend,
+    image: &ImageEntity,
+) -> Result<Vec<u8>> {
+    let mut last = None;
+    for url in image.urls(storage) {
+        let rst = async {
+            Ok(client.get(&url).send().await?.error_for_status()?.bytes().await?.to_vec())
+        };
+        match rst.await {
+            Ok(bytes) => return Ok(bytes),
+            Err(err) => last = Some(err),
+        }
+    }
+    Err(last.unwrap_or_else(|| anyhow!("没有可用的地址")))
+}
+
+async fn flatten<T>(handle: JoinHandle<Result<T>>) -> Result<T> {
+    match handle.await {
+        Ok(Ok(result)) => Ok(result),
+        Ok(Err(err)) => Err(err),
+        Err(err) => bail!(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+
+    fn serve(routes: Vec<(&'static str, &'static [u8])>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).unwrap();
+                let req = String::from_utf8_lossy(&buf[..n]);
+                let path = req.split_whitespace().nth(1).unwrap_or_default();
+                let resp = match routes.iter().find(|(p, _)| *p == path) {
+                    Some((_, body)) => {
+                        let mut resp =
+                            format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len())
+                                .into_bytes();
+                        resp.extend_from_slice(body);
+                        resp
+                    }
+                    None => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec(),
+                };
+                stream.write_all(&resp).unwrap();
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    fn page_of(body: &[u8]) -> EhPageUrl {
+        let sha1 = format!("{:x}", Sha1::digest(body));
+        format!("https://exhentai.org/s/{}/1-1", &sha1[..10]).parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn download_rejects_prefix_mismatch() {
+        let host = serve(vec![("/good.jpg", b"good"), ("/bad.jpg", b"bad")]);
+        let page = page_of(b"good");
+        let client = Client::new();
+
+        let (bytes, sha1) =
+            download_verified(&client, &page, &format!("{}/good.jpg", host)).await.unwrap();
+        assert_eq!(bytes, b"good");
+        assert_eq!(sha1, format!("{:x}", Sha1::digest(b"good")));
+        assert!(download_verified(&client, &page, &format!("{}/bad.jpg", host)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn download_retries_with_nl() {
+        let host = serve(vec![("/good.jpg", b"good"), ("/bad.jpg", b"bad")]);
+        let page = page_of(b"good");
+        let client = Client::new();
+
+        // 第一次下载到的内容校验失败，通过 nl 重新获取的地址下载成功
+        let fallback = async { Ok(format!("{}/good.jpg", host)) };
+        let (url, bytes, _) =
+            download_image(&client, &page, format!("{}/bad.jpg", host), fallback).await.unwrap();
+        assert_eq!(url, format!("{}/good.jpg", host));
+        assert_eq!(bytes, b"good");
+
+        // 第一次请求失败也会重试
+        let fallback = async { Ok(format!("{}/good.jpg", host)) };
+        let rst = download_image(&client, &page, format!("{}/missing.jpg", host), fallback).await;
+        assert_eq!(rst.unwrap().1, b"good");
+
+        // 重试后依然校验失败则返回错误
+        let fallback = async { Ok(format!("{}/bad.jpg", host)) };
+        let rst = download_image(&client, &page, format!("{}/bad.jpg", host), fallback).await;
+        assert!(rst.is_err());
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use reqwest::Client;
+use sha1::{Digest, Sha1};
+use teloxide::prelude::*;
+use teloxide::types::MessageId;
+use tokio::time;
+use tracing::{error, info, warn};
+
+use super::ExloliUploader;
+use crate::database::{ContactSheetEntity, GalleryEntity, ImageEntity, MessageEntity, PageEntity};
+use crate::storage::Storage;
+
+/// 迁移 telegraph 图片的统计
+#[derive(Debug, Default)]
+pub struct MigrateStats {
+    pub migrated: usize,
+    pub failed: usize,
+    pub republished: usize,
+}
+
+impl std::fmt::Display for MigrateStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "已迁移 {} 张图片，失败 {} 张，重新发布 {} 篇文章",
+            self.migrated, self.failed, self.republished
+        )
+    }
+}
+
+impl ExloliUploader {
+    /// 根据配置中的地址，为旧数据中记录了完整地址的图片和缩略图拆分出对象键
+    ///
+    /// 认不出地址的文件保持原样，继续使用原来的地址
+    pub(super) async fn migrate_storage_keys(&self) -> Result<()> {
+        let (mut after, mut unknown) = (0, 0);
+        loop {
+            let images = ImageEntity::list_without_key(after, 1000).await?;
+            match images.last() {
+                Some(img) => after = img.id,
+                None => break,
+            }
+            for image in images {
+                match self.storage.split_url(&image.url(&self.storage)) {
+                    Some((storage, key)) => {
+                        ImageEntity::update_key(image.id, storage, &key).await?;
+                    }
+                    None => unknown += 1,
+                }
+            }
+        }
+        for sheet in ContactSheetEntity::list_without_key().await? {
+            match self.storage.split_url(&sheet.url(&self.storage)) {
+                Some((storage, key)) => {
+                    ContactSheetEntity::update_key(sheet.gallery_id, storage, &key).await?;
+                }
+                None => unknown += 1,
+            }
+        }
+        if unknown > 0 {
+            warn!("{} 个旧文件的地址与配置中的地址不符，继续使用原来的地址", unknown);
+        }
+        Ok(())
+    }
+
+    /// 将早期存放在 telegraph 上的图片迁移到自己的存储中，并重新发布受影响的文章
+    ///
+    /// 已迁移的图片会直接改写地址，因此中断后再次执行会从未迁移的图片继续；
+    /// 每张图片之间会等待一段时间，避免触发 telegraph 的限制；已经有迁移在进行时直接返回错误
+    pub async fn migrate_legacy_images(
+        &self,
+        progress: Option<(ChatId, MessageId)>,
+    ) -> Result<MigrateStats> {
+        let _guard = self.migrate_lock.try_lock().map_err(|_| anyhow!("已经有迁移正在进行"))?;
+        let client = Client::builder().timeout(Duration::from_secs(30)).build()?;
+        let mut stats = MigrateStats::default();
+        let mut after = 0;
+        loop {
+            let images = ImageEntity::list_legacy(after, 100).await?;
+            match images.last() {
+                Some(img) => after = img.id,
+                None => break,
+            }
+            let mut galleries = HashSet::new();
+            for image in images {
+                match self.migrate_image(&client, &image).await {
+                    Ok(()) => {
+                        stats.migrated += 1;
+                        let pages = PageEntity::get_by_image(image.id).await?;
+                        galleries.extend(pages.into_iter().map(|p| p.gallery_id));
+                    }
+                    Err(err) => {
+                        stats.failed += 1;
+                        error!("迁移图片失败：{} {}", image.key, err);
+                    }
+                }
+                time::sleep(Duration::from_millis(500)).await;
+            }
+            // 每批迁移完成后立即更新文章，保证中断时已迁移的部分也是完整的
+            for id in galleries {
+                let gallery = match GalleryEntity::get(id).await? {
+                    Some(v) => v,
+                    None => continue,
+                };
+                if MessageEntity::get_by_gallery(id).await?.is_none() {
+                    continue;
+                }
+                match self.republish(&gallery).await {
+                    Ok(()) => stats.republished += 1,
+                    Err(err) => error!("重新发布失败：{} {}", id, err),
+                }
+            }
+            info!("图片迁移进度：{}", after);
+            if let Some((chat, msg)) = progress {
+                let text = format!("迁移中……\n{}", stats);
+                if let Err(err) = self.bot.edit_message_text(chat, msg, text).await {
+                    warn!("更新进度失败：{}", err);
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    async fn migrate_image(&self, client: &Client, image: &ImageEntity) -> Result<()> {
+        let url = image.url(&self.storage);
+        let bytes = client.get(&url).send().await?.error_for_status()?.bytes().await?;
+        let sha1 = format!("{:x}", Sha1::digest(&bytes));
+        if !sha1.starts_with(&image.hash) {
+            bail!("图片校验失败");
+        }
+        let suffix = match url.rsplit('.').next() {
+            Some(suffix @ ("jpg" | "png" | "webp")) => suffix,
+            _ => bail!("不支持的图片格式"),
+        };
+        let filename = format!("{}.{}", image.hash, suffix);
+        self.storage.put(&filename, &bytes).await?;
+        ImageEntity::update_key(image.id, self.storage.id(), &filename).await?;
+        ImageEntity::update_integrity(image.id, bytes.len() as i64, None).await?;
+        Ok(())
+    }
+}
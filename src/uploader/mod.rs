@@ -0,0 +1,398 @@
+//! 画廊的扫描、上传与发布
+//!
+//! 定时扫描 E 站，上传画廊的图片并生成预览文章，然后根据上传规则发送到各个频道
+
+mod contact_sheet;
+mod duplicate;
+mod images;
+mod migrate;
+mod post;
+mod preview;
+mod queue;
+mod review;
+mod schedule;
+mod verify;
+
+use std::backtrace::Backtrace;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use chrono::Utc;
+use futures::StreamExt;
+use tokio::sync::Mutex;
+use tokio::time;
+use tracing::{error, info, warn};
+
+pub use self::migrate::MigrateStats;
+pub use self::verify::VerifyStats;
+use crate::bot::Bot;
+use crate::config::{Channel, Config, DuplicateAction, Filter, RuleAction};
+use crate::database::{
+    GalleryEntity, GalleryHistoryEntity, GalleryRelationEntity, GalleryStatsEntity, MessageEntity,
+    MessageKind, PollEntity, QueueEntity, RelationKind, ReviewEntity, TelegraphAccountEntity,
+    TelegraphEntity, UpdateScheduleEntity,
+};
+use crate::ehentai::{EhClient, EhGalleryUrl, GalleryInfo};
+use crate::publisher::{PreviewPublisher, Publisher};
+use crate::rules;
+use crate::storage::StorageBackend;
+use crate::tags::EhTagTransDB;
+use crate::template::MessageTemplate;
+
+/// telegram 文本消息的长度上限
+const MAX_MESSAGE_LEN: usize = 4096;
+/// telegram 图片说明的长度上限
+const MAX_CAPTION_LEN: usize = 1024;
+
+/// 各类消息正文的长度上限
+fn text_limit(kind: MessageKind) -> usize {
+    match kind {
+        MessageKind::Text => MAX_MESSAGE_LEN,
+        MessageKind::Photo => MAX_CAPTION_LEN,
+    }
+}
+
+/// 分篇文章的标题，拆分为多篇时在标题后加上序号
+fn part_title(title: &str, i: usize, n: usize) -> String {
+    match n {
+        1 => title.to_owned(),
+        n => format!("{} ({}/{})", title, i + 1, n),
+    }
+}
+
+/// 发布画廊的频道，以及该频道的上传规则和消息模板
+#[derive(Debug)]
+struct PostChannel {
+    channel: Channel,
+    filter: Filter,
+    template: MessageTemplate,
+}
+
+impl PostChannel {
+    fn new(channel: &Channel, filter: &Filter) -> Result<Self> {
+        Ok(Self {
+            channel: channel.clone(),
+            filter: filter.clone(),
+            template: MessageTemplate::new(channel.template.as_deref())?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExloliUploader {
+    ehentai: EhClient,
+    publisher: Publisher,
+    storage: StorageBackend,
+    bot: Bot,
+    config: Config,
+    trans: EhTagTransDB,
+    /// 所有频道，第一个为主频道
+    channels: Arc<Vec<PostChannel>>,
+    /// 发布队列的锁，避免定时发布与管理员手动发布同时进行
+    queue_lock: Arc<Mutex<()>>,
+    /// 迁移 telegraph 图片的锁，启动时的自动迁移与 /migrate_images 同一时间只能运行一个
+    migrate_lock: Arc<Mutex<()>>,
+}
+
+impl ExloliUploader {
+    pub async fn new(
+        config: Config,
+        ehentai: EhClient,
+        bot: Bot,
+        trans: EhTagTransDB,
+    ) -> Result<Self> {
+        let storage = StorageBackend::new(&config)?;
+        let publisher = Publisher::new(&config, &storage).await?;
+        let mut channels = vec![PostChannel::new(&config.telegram.main, &config.filter)?];
+        for routed in &config.telegram.channels {
+            channels.push(PostChannel::new(&routed.channel, &routed.filter)?);
+        }
+        let channels = Arc::new(channels);
+        Ok(Self {
+            ehentai,
+            config,
+            publisher,
+            storage,
+            bot,
+            trans,
+            channels,
+            queue_lock: Default::default(),
+            migrate_lock: Default::default(),
+        })
+    }
+
+    /// 图片等文件的存储
+    pub fn storage(&self) -> &StorageBackend {
+        &self.storage
+    }
+
+    /// 每隔 interval 分钟检查一次
+    pub async fn start(&self) {
+        if let Err(err) = self.migrate_storage_keys().await {
+            error!("拆分旧文件的对象键失败：{}", err);
+        }
+        if let Some(queue) = self.config.queue.clone() {
+            let uploader = self.clone();
+            tokio::spawn(async move { uploader.run_queue(queue).await });
+        }
+        let uploader = self.clone();
+        tokio::spawn(async move { uploader.run_refresher().await });
+        // 启动时继续迁移尚未迁移的 telegraph 图片
+        let uploader = self.clone();
+        tokio::spawn(async move {
+            if let Err(err) = uploader.migrate_legacy_images(None).await {
+                error!("迁移 telegraph 图片失败：{}", err);
+            }
+        });
+        loop {
+            info!("开始扫描 E 站 本子");
+            self.check().await;
+            info!("扫描完毕，等待 {:?} 后继续", self.config.interval);
+            time::sleep(self.config.interval).await;
+        }
+    }
+
+    /// 根据配置文件，扫描前 N 个本子，并进行上传或者更新
+    #[tracing::instrument(skip(self))]
+    async fn check(&self) {
+        if let Err(err) = self.expire_reviews().await {
+            error!("处理过期审核失败：{}", err);
+        }
+        let stream = self
+            .ehentai
+            .search_iter(&self.config.exhentai.search_params)
+            .take(self.config.exhentai.search_count);
+        tokio::pin!(stream);
+        while let Some(next) = stream.next().await {
+            // 错误不要上抛，避免影响后续画廊
+            if let Err(err) = self.try_update(&next, true).await {
+                error!("check_and_update: {:?}\n{}", err, Backtrace::force_capture());
+            }
+            if let Err(err) = self.try_upload(&next, true).await {
+                error!("check_and_upload: {:?}\n{}", err, Backtrace::force_capture());
+            }
+            time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    /// 检查指定画廊是否已经上传，如果没有则进行上传
+    ///
+    /// 为了避免绕晕自己，这次不考虑父子画廊，只要 id 不同就视为新画廊，只要是新画廊就进行上传
+    ///
+    /// check 为 false 时，会跳过所有检查强制上传
+    #[tracing::instrument(skip(self))]
+    pub async fn try_upload(&self, gallery: &EhGalleryUrl, check: bool) -> Result<()> {
+        if check
+            && GalleryEntity::check(gallery.id()).await?
+            && MessageEntity::get_by_gallery(gallery.id()).await?.is_some()
+        {
+            return Ok(());
+        }
+
+        // 被判定为重复，或者正在等待管理员决定的画廊，不需要再处理
+        let relation = GalleryRelationEntity::get(gallery.id()).await?;
+        if check
+            && relation
+                .as_ref()
+                .is_some_and(|r| matches!(r.kind, RelationKind::Pending | RelationKind::Duplicate))
+        {
+            return Ok(());
+        }
+
+        // 被上传规则暂缓发布的画廊等待管理员处理，被规则跳过的画廊不再处理
+        if check && ReviewEntity::get(gallery.id()).await?.is_some() {
+            return Ok(());
+        }
+
+        // 已经在发布队列中的画廊
+        if check && QueueEntity::get(gallery.id()).await?.is_some() {
+            return Ok(());
+        }
+
+        let gallery = self.ehentai.get_gallery(gallery).await?;
+
+        // 根据上传规则决定如何处理，手动上传时不检查
+        let mut hold = None;
+        if check {
+            let eval = rules::evaluate(&self.config.filter, &gallery, Utc::now().naive_utc());
+            let rule = eval.rule.as_deref().unwrap_or("默认");
+            info!("上传规则：{} -> {}", rule, eval.action);
+            match eval.action {
+                // 需要审核的画廊同样要先检查是否重复，再发送审核消息
+                RuleAction::Hold => hold = Some(rule.to_owned()),
+                // 主频道跳过、其他频道也没有选中时，记录跳过的规则
+                _ if self.route(&eval, &gallery, false).is_empty() => {
+                    ReviewEntity::skip(gallery.url.id(), rule).await?;
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
+        // 上传图片
+        self.upload_gallery_image(&gallery).await?;
+        if let Err(err) = self.create_contact_sheet(gallery.url.id()).await {
+            warn!("生成缩略图失败：{}", err);
+        }
+
+        // 检查是否与已经发布过的画廊重复，已经有过决定的画廊不需要重复检查
+        if let (true, None, Some(cfg)) = (check, &relation, &self.config.duplicate) {
+            if let Some((related, overlap)) = self.find_duplicate(&gallery, cfg).await? {
+                info!("与画廊 {} 的重合度为 {:.2}", related, overlap);
+                let id = gallery.url.id();
+                match cfg.action {
+                    DuplicateAction::Skip => {
+                        GalleryRelationEntity::create(
+                            id,
+                            related,
+                            RelationKind::Duplicate,
+                            overlap,
+                        )
+                        .await?;
+                        GalleryEntity::create(&gallery).await?;
+                        return Ok(());
+                    }
+                    DuplicateAction::Reply => {
+                        GalleryRelationEntity::create(id, related, RelationKind::Version, overlap)
+                            .await?;
+                    }
+                    DuplicateAction::Ask => {
+                        GalleryRelationEntity::create(id, related, RelationKind::Pending, overlap)
+                            .await?;
+                        GalleryEntity::create(&gallery).await?;
+                        self.ask_duplicate(&gallery, related, overlap).await?;
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        if let Some(rule) = hold {
+            return self.hold(&gallery, &rule).await;
+        }
+
+        // 发布文章、发送消息，启用发布队列时，扫描到的画廊只生成文章，等待按计划发布
+        if check && self.config.queue.is_some() {
+            self.enqueue(&gallery).await
+        } else {
+            self.publish(&gallery, !check).await
+        }
+    }
+
+    /// 检查指定画廊是否有更新，比如标题、标签，并安排下一次检查
+    ///
+    /// check 为 true 时，只有到了计划的检查时间才会检查，见 [`Self::schedule_update`]
+    #[tracing::instrument(skip(self))]
+    pub async fn try_update(&self, gallery: &EhGalleryUrl, check: bool) -> Result<()> {
+        let entity = match GalleryEntity::get(gallery.id()).await? {
+            Some(v) => v,
+            _ => return Ok(()),
+        };
+        let messages = MessageEntity::list_by_gallery(gallery.id()).await?;
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        if check {
+            let schedule = UpdateScheduleEntity::get(gallery.id()).await?;
+            if schedule.is_some_and(|s| s.next_check_at > Utc::now().naive_utc()) {
+                return Ok(());
+            }
+        }
+
+        // 检查 tag 和标题是否有变化
+        let gallery = self.ehentai.get_gallery(gallery).await?;
+
+        let now = Utc::now().naive_utc();
+        GalleryStatsEntity::create(&gallery, now).await?;
+        let history = GalleryHistoryEntity::diff(&entity, &gallery, now);
+        if let Some(history) = &history {
+            info!("画廊发生变化：{}", gallery.url);
+            history.create().await?;
+            let telegraph = TelegraphEntity::get(gallery.url.id()).await?.unwrap();
+            self.edit_posts(&gallery, &telegraph.url, &messages).await?;
+            self.send_changelog(history, &messages).await;
+        }
+
+        GalleryEntity::create(&gallery).await?;
+        self.schedule_update(gallery.url.id(), history.is_some()).await?;
+
+        Ok(())
+    }
+
+    /// 重新扫描并上传没有上传过但存在记录的画廊
+    pub async fn reupload(&self, mut galleries: Vec<GalleryEntity>) -> Result<()> {
+        if galleries.is_empty() {
+            galleries = GalleryEntity::list_scans().await?;
+        }
+        for gallery in galleries.iter().rev() {
+            if let Some(score) = PollEntity::get_by_gallery(gallery.id).await? {
+                if score.score > 0.8 {
+                    info!("尝试上传画廊：{}", gallery.url());
+                    if let Err(err) = self.try_upload(&gallery.url(), true).await {
+                        error!("上传失败：{}", err);
+                    }
+                    time::sleep(Duration::from_secs(60)).await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 重新发布指定画廊的文章，并更新各频道的消息
+    ///
+    /// 已有的文章会被原地编辑，只有文章失效时才会新建，此时才需要更新消息中的链接
+    pub async fn republish(&self, gallery: &GalleryEntity) -> Result<()> {
+        info!("重新发布：{}", gallery.id);
+        let old = TelegraphEntity::get(gallery.id).await?;
+        let paths = old.as_ref().map(|t| t.paths()).unwrap_or_default();
+        let pages = self.publish_article(gallery, &paths).await?;
+        let parts = pages.iter().map(|p| p.url.clone()).collect::<Vec<_>>();
+        if old.map(|t| t.url) != Some(parts[0].clone()) {
+            let messages = MessageEntity::list_by_gallery(gallery.id).await?;
+            self.edit_posts(gallery, &parts[0], &messages).await?;
+        }
+        let paths = pages.iter().map(|p| p.path.clone()).collect::<Vec<_>>();
+        TelegraphEntity::create(gallery.id, &parts, &paths).await?;
+        Ok(())
+    }
+
+    /// 画廊的图片发生变化（如被标记为广告）后，更新已发布的文章
+    pub async fn refresh_article(&self, gallery_id: i32) -> Result<()> {
+        let gallery = match GalleryEntity::get(gallery_id).await? {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        if MessageEntity::get_by_gallery(gallery_id).await?.is_some() {
+            self.republish(&gallery).await?;
+        }
+        Ok(())
+    }
+
+    /// 使用各频道的上传规则检查指定画廊，返回匹配过程
+    pub async fn explain(&self, url: &EhGalleryUrl) -> Result<String> {
+        let gallery = self.ehentai.get_gallery(url).await?;
+        let now = Utc::now().naive_utc();
+        let mut text = rules::evaluate(&self.config.filter, &gallery, now).to_string();
+        for ch in self.channels.iter().skip(1) {
+            let name = ch.channel.name.clone().unwrap_or_else(|| ch.channel.key());
+            let eval = rules::evaluate(&ch.filter, &gallery, now);
+            text.push_str(&format!("\n\n频道 {}：\n{}", name, eval));
+        }
+        Ok(text)
+    }
+
+    /// 检查预览页面是否正常
+    pub async fn check_preview(&self, url: &str) -> Result<bool> {
+        self.publisher.check(url).await
+    }
+
+    /// 列出 telegraph 账号池中的所有账号，以及各自的文章数量
+    pub async fn telegraph_accounts(&self) -> Result<Vec<(TelegraphAccountEntity, Option<i32>)>> {
+        match &self.publisher {
+            Publisher::Telegraph(publisher) => publisher.accounts().await,
+            _ => bail!("当前没有使用 telegraph 发布预览"),
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use anyhow::{bail, Result};
+use chrono::Utc;
+use reqwest::Client;
+use telegraph_rs::Node;
+use teloxide::prelude::*;
+use teloxide::types::{InputFile, MessageId};
+use teloxide::utils::html::escape;
+use tracing::warn;
+
+use super::images::download_stored;
+use super::{text_limit, ExloliUploader, PostChannel, MAX_CAPTION_LEN, MAX_MESSAGE_LEN};
+use crate::article;
+use crate::bot::{channel_recipient, gallery_preview_url};
+use crate::config::{ArticleHeader, RuleAction};
+use crate::database::{
+    GalleryEntity, GalleryHistoryEntity, GalleryRelationEntity, GalleryStatsEntity, ImageEntity,
+    ImageFlag, MessageEntity, MessageKind, PollEntity, RelationKind, ReviewEntity, TelegraphEntity,
+};
+use crate::ehentai::{EhGallery, EhGalleryUrl, GalleryInfo};
+use crate::publisher::{PreviewPage, PreviewPublisher};
+use crate::rules;
+use crate::template::{MessageContext, TagGroup};
+use crate::utils::html_text_len;
+
+impl ExloliUploader {
+    /// 发布文章，并向上传规则选中的频道发送消息，最后将数据入库
+    ///
+    /// 审核时已经生成过的文章会被原地更新，已经发布过的频道不会重复发送
+    pub(super) async fn publish(&self, gallery: &EhGallery, force: bool) -> Result<()> {
+        let paths =
+            TelegraphEntity::get(gallery.url.id()).await?.map(|t| t.paths()).unwrap_or_default();
+        let (article, header_len) = self.build_article(gallery).await?;
+        let pages = self.publish_parts(&gallery.title_jp(), &article, &paths).await?;
+        let parts = pages.iter().map(|p| p.url.clone()).collect::<Vec<_>>();
+        let paths = pages.iter().map(|p| p.path.clone()).collect::<Vec<_>>();
+        TelegraphEntity::create(gallery.url.id(), &parts, &paths).await?;
+        GalleryEntity::create(gallery).await?;
+        self.post(gallery, force, &pages, &article[0], header_len).await
+    }
+
+    /// 根据数据库中记录的频道 ID 查找频道
+    fn channel_of(&self, key: &str) -> Option<&PostChannel> {
+        self.channels.iter().find(|ch| ch.channel.key() == key)
+    }
+
+    /// 根据各频道的上传规则，决定画廊需要发布到哪些频道，返回频道的下标
+    ///
+    /// eval 为主频道规则的匹配结果，判定为 hold 时也视为发布，因为只有审核通过的画廊才会走到发布这一步；
+    /// force 为 true 时，无论主频道的规则如何都会发布到主频道
+    pub(super) fn route(
+        &self,
+        eval: &rules::Evaluation,
+        gallery: &EhGallery,
+        force: bool,
+    ) -> Vec<usize> {
+        let now = Utc::now().naive_utc();
+        let mut targets = vec![];
+        match &eval.action {
+            RuleAction::Upload | RuleAction::Hold => targets.push(0),
+            RuleAction::Skip => {}
+            RuleAction::Route(name) => {
+                match self.channels.iter().position(|ch| ch.channel.is_named(name)) {
+                    Some(idx) => targets.push(idx),
+                    None => warn!("频道 {} 不存在，跳过", name),
+                }
+            }
+        }
+        if force && !targets.contains(&0) {
+            targets.insert(0, 0);
+        }
+        for (idx, ch) in self.channels.iter().enumerate().skip(1) {
+            if !targets.contains(&idx)
+                && rules::evaluate(&ch.filter, gallery, now).action == RuleAction::Upload
+            {
+                targets.push(idx);
+            }
+        }
+        targets
+    }
+
+    /// 向上传规则选中的频道发送指向已发布文章的消息
+    ///
+    /// first 为第一篇文章的内容，header_len 为其中开头画廊信息的节点数，用于在发送消息后补上频道消息的链接
+    pub(super) async fn post(
+        &self,
+        gallery: &EhGallery,
+        force: bool,
+        pages: &[PreviewPage],
+        first: &[Node],
+        header_len: usize,
+    ) -> Result<()> {
+        let parts = pages.iter().map(|p| p.url.clone()).collect::<Vec<_>>();
+        // 进入发布流程后规则发生了变化时，至少要发布到主频道
+        let eval = rules::evaluate(&self.config.filter, gallery, Utc::now().naive_utc());
+        let mut targets = self.route(&eval, gallery, force);
+        if targets.is_empty() {
+            targets.push(0);
+        }
+        for idx in targets {
+            let ch = &self.channels[idx];
+            let key = ch.channel.key();
+            if MessageEntity::get_by_gallery_in(&key, gallery.url.id()).await?.is_some() {
+                continue;
+            }
+            let reply_to = self.reply_target(gallery, &key).await?;
+            let (msg, kind) = self.send_post(gallery, &parts[0], reply_to, ch).await?;
+            MessageEntity::create(msg.id.0, &key, gallery.url.id(), kind).await?;
+        }
+        GalleryStatsEntity::create(gallery, Utc::now().naive_utc()).await?;
+        self.schedule_update(gallery.url.id(), true).await?;
+        // 发送消息后，补上第一篇文章开头的频道消息链接
+        if self.config.telegraph.header.contains(&ArticleHeader::Links) {
+            let mut nodes = self.article_header(gallery).await?;
+            nodes.extend(first.iter().skip(header_len).cloned());
+            if pages.len() > 1 {
+                let nav = article::article_nav(&parts, 0);
+                nodes.insert(0, nav.clone());
+                nodes.push(nav);
+            }
+            self.publisher.edit(&pages[0].path, &pages[0].title, &nodes).await?;
+        }
+        Ok(())
+    }
+
+    /// 发送频道消息
+    ///
+    /// 启用图片消息时，会以封面作为图片、按说明的长度上限删减后的正文作为说明发送，图片发送失败时改为发送纯文本消息
+    async fn send_post<T: GalleryInfo>(
+        &self,
+        gallery: &T,
+        article: &str,
+        reply_to: Option<i32>,
+        ch: &PostChannel,
+    ) -> Result<(Message, MessageKind)> {
+        let channel = ch.channel.channel_id.clone();
+        if ch.channel.photo.unwrap_or(false) {
+            match self.cover_photo(gallery).await {
+                Ok(Some(photo)) => {
+                    let caption =
+                        self.create_message_text(gallery, article, MAX_CAPTION_LEN, ch).await?;
+                    let req = self
+                        .bot
+                        .send_photo(channel.clone(), InputFile::memory(photo))
+                        .caption(caption);
+                    let req = match reply_to {
+                        Some(id) => req.reply_to_message_id(MessageId(id)),
+                        None => req,
+                    };
+                    match req.await {
+                        Ok(msg) => return Ok((msg, MessageKind::Photo)),
+                        Err(err) => warn!("发送图片消息失败：{}", err),
+                    }
+                }
+                Ok(None) => warn!("找不到可用的封面，改为发送纯文本消息"),
+                Err(err) => warn!("获取封面失败：{}", err),
+            }
+        }
+        let text = self.create_message_text(gallery, article, MAX_MESSAGE_LEN, ch).await?;
+        let req = self.bot.send_message(channel, text);
+        let msg = match reply_to {
+            Some(id) => req.reply_to_message_id(MessageId(id)).await?,
+            None => req.await?,
+        };
+        Ok((msg, MessageKind::Text))
+    }
+
+    /// 使用各频道的模板重新生成消息正文，并编辑画廊在所有频道中的消息
+    pub(super) async fn edit_posts<T: GalleryInfo>(
+        &self,
+        gallery: &T,
+        article: &str,
+        messages: &[MessageEntity],
+    ) -> Result<()> {
+        for msg in messages {
+            // 已经从配置中移除的频道，使用主频道的模板
+            let ch = self.channel_of(&msg.channel_id).unwrap_or(&self.channels[0]);
+            let limit = text_limit(msg.kind);
+            let text = self.create_message_text(gallery, article, limit, ch).await?;
+            self.edit_post(msg, text).await?;
+        }
+        Ok(())
+    }
+
+    /// 在启用了更新日志的频道中，回复画廊消息在讨论组中的转发，说明画廊发生了哪些变化
+    pub(super) async fn send_changelog(
+        &self,
+        history: &GalleryHistoryEntity,
+        messages: &[MessageEntity],
+    ) {
+        let text = format!("画廊已更新\n{}", escape(&history.to_string()));
+        for msg in messages {
+            let (ch, thread) = match (self.channel_of(&msg.channel_id), msg.thread_id) {
+                (Some(ch), Some(thread)) if ch.channel.changelog.unwrap_or(false) => (ch, thread),
+                _ => continue,
+            };
+            let req = self
+                .bot
+                .send_message(ch.channel.group_id, text.clone())
+                .reply_to_message_id(MessageId(thread))
+                .disable_web_page_preview(true);
+            if let Err(err) = req.await {
+                warn!("发送更新日志失败：{}", err);
+            }
+        }
+    }
+
+    /// 编辑频道消息的正文，图片消息则编辑其说明
+    async fn edit_post(&self, msg: &MessageEntity, text: String) -> Result<()> {
+        let channel = channel_recipient(&msg.channel_id);
+        match msg.kind {
+            MessageKind::Text => {
+                self.bot.edit_message_text(channel, MessageId(msg.id), text).await?;
+            }
+            MessageKind::Photo => {
+                if html_text_len(&text) > MAX_CAPTION_LEN {
+                    bail!("消息过长，无法作为图片说明");
+                }
+                self.bot.edit_message_caption(channel, MessageId(msg.id)).caption(text).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 下载画廊的封面，封面被标记为广告或无效时，使用第一张正常的图片
+    pub(super) async fn cover_photo<T: GalleryInfo>(&self, gallery: &T) -> Result<Option<Vec<u8>>> {
+        let images = ImageEntity::get_by_gallery_id(gallery.url().id()).await?;
+        let cover = match images.get(gallery.cover()) {
+            Some(img) if img.flag == ImageFlag::Ok => Some(img),
+            _ => images.iter().find(|img| img.flag == ImageFlag::Ok),
+        };
+        let cover = match cover {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        Ok(Some(download_stored(&Client::new(), &self.storage, cover).await?))
+    }
+
+    /// 获取在指定频道发送消息时需要回复的消息 ID
+    ///
+    /// 如果父画廊已经发布过，则回复父画廊；如果被标记为某个画廊的另一个版本，则回复该画廊
+    async fn reply_target(&self, gallery: &EhGallery, channel: &str) -> Result<Option<i32>> {
+        // FIXME: 此处没有考虑到父画廊没有上传，但是父父画廊上传过的情况
+        // 不过一般情况下画廊应该不会那么短时间内更新多次
+        if let Some(parent) = &gallery.parent {
+            if let Some(pmsg) = MessageEntity::get_by_gallery_in(channel, parent.id()).await? {
+                return Ok(Some(pmsg.id));
+            }
+        }
+        if let Some(relation) = GalleryRelationEntity::get(gallery.url.id()).await? {
+            if relation.kind == RelationKind::Version {
+                let related = relation.related_id;
+                if let Some(msg) = MessageEntity::get_by_gallery_in(channel, related).await? {
+                    return Ok(Some(msg.id));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// 为画廊生成一条可供发送到指定频道的 telegram 消息正文，格式由该频道的模板决定
+    ///
+    /// 消息超出长度上限时，会逐步删减标签，见 [`MessageTemplate::render_fit`]
+    pub(super) async fn create_message_text<T: GalleryInfo>(
+        &self,
+        gallery: &T,
+        article: &str,
+        limit: usize,
+        ch: &PostChannel,
+    ) -> Result<String> {
+        let id = gallery.url().id();
+        let tags = gallery
+            .tags()
+            .iter()
+            .map(|(ns, tags)| TagGroup {
+                namespace: self.trans.trans_namespace(ns),
+                raw_namespace: ns.clone(),
+                tags: tags.iter().flat_map(|t| self.trans.trans(ns, t)).collect(),
+                raw_tags: tags.clone(),
+                more: 0,
+            })
+            .collect();
+        let score = PollEntity::get_by_gallery(id).await?.map(|poll| poll.score * 100.);
+        let parent = match gallery.parent() {
+            Some(parent) => gallery_preview_url(&ch.channel, parent).await.ok(),
+            None => None,
+        };
+        let ctx = MessageContext {
+            id,
+            url: gallery.url().url(),
+            title: gallery.title(),
+            title_jp: gallery.title_jp(),
+            article: article.to_owned(),
+            pages: gallery.pages(),
+            uploader: gallery.uploader(),
+            posted: gallery.posted().map(|t| t.format("%Y-%m-%d %H:%M").to_string()),
+            score,
+            parent,
+            note: ReviewEntity::get(id).await?.and_then(|r| r.note),
+            tags,
+        };
+        ch.template.render_fit(&ctx, limit, &ch.channel.low_priority)
+    }
+
+    /// 使用主频道当前的模板渲染指定画廊的消息，但不发送
+    ///
+    /// 已经上传过的画廊使用数据库中的信息，否则从 E 站获取
+    pub async fn preview_message(&self, url: &EhGalleryUrl) -> Result<String> {
+        let article = TelegraphEntity::get(url.id())
+            .await?
+            .map(|t| t.url)
+            .unwrap_or_else(|| "https://telegra.ph/".to_owned());
+        match GalleryEntity::get(url.id()).await? {
+            Some(gallery) => {
+                self.create_message_text(&gallery, &article, MAX_MESSAGE_LEN, &self.channels[0])
+                    .await
+            }
+            None => {
+                let gallery = self.ehentai.get_gallery(url).await?;
+                self.create_message_text(&gallery, &article, MAX_MESSAGE_LEN, &self.channels[0])
+                    .await
+            }
+        }
+    }
+}
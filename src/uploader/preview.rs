@@ -0,0 +1,237 @@
+use anyhow::{anyhow, Result};
+use reqwest::Url;
+use telegraph_rs::Node;
+use tracing::{info, warn};
+
+use super::{part_title, ExloliUploader};
+use crate::article;
+use crate::bot::{channel_recipient, gallery_preview_url, url_of};
+use crate::config::ArticleHeader;
+use crate::database::{
+    ContactSheetEntity, GalleryEntity, GalleryRelationEntity, ImageEntity, ImageFlag,
+    MessageEntity, PollEntity, RelationKind,
+};
+use crate::ehentai::GalleryInfo;
+use crate::publisher::{is_page_not_found, PreviewPage, PreviewPublisher};
+
+impl ExloliUploader {
+    /// 从数据库中读取某个画廊的所有图片，生成预览文章
+    /// 为了防止画廊被删除后无法更新，此处不应该依赖 EhGallery
+    ///
+    /// 图片过多时，文章会超出发布方式的大小限制，此时会拆分为多篇，并在每篇的首尾加上前后篇的链接
+    ///
+    /// 如果传入了已有文章的路径，则会优先原地编辑这些文章，保证链接不变
+    pub(super) async fn publish_article<T: GalleryInfo>(
+        &self,
+        gallery: &T,
+        paths: &[String],
+    ) -> Result<Vec<PreviewPage>> {
+        let (parts, _) = self.build_article(gallery).await?;
+        self.publish_parts(&gallery.title_jp(), &parts, paths).await
+    }
+
+    /// 生成文章内容并按发布方式的大小限制拆分，同时返回开头画廊信息的节点数
+    pub(super) async fn build_article<T: GalleryInfo>(
+        &self,
+        gallery: &T,
+    ) -> Result<(Vec<Vec<Node>>, usize)> {
+        let images = ImageEntity::get_by_gallery_id(gallery.url().id()).await?;
+
+        let mut nodes = self.article_header(gallery).await?;
+        let header_len = nodes.len();
+        if gallery.cover() != 0
+            && gallery.cover() < images.len()
+            && images[gallery.cover()].flag == ImageFlag::Ok
+        {
+            nodes.push(article::img(&images[gallery.cover()].url(&self.storage)));
+        }
+        // 跳过被标记为广告或无效的图片
+        for img in images.iter().filter(|img| img.flag == ImageFlag::Ok) {
+            nodes.push(article::img(&img.url(&self.storage)));
+        }
+        nodes.extend(self.article_footer(gallery).await?);
+
+        let parts = article::split_article(nodes, self.publisher.max_size());
+        if parts.len() > 1 {
+            info!("文章过长，拆分为 {} 篇", parts.len());
+        }
+        Ok((parts, header_len))
+    }
+
+    /// 发布拆分好的各篇文章，文章标题优先使用日文
+    pub(super) async fn publish_parts(
+        &self,
+        title: &str,
+        parts: &[Vec<Node>],
+        paths: &[String],
+    ) -> Result<Vec<PreviewPage>> {
+        // 先发布所有分篇，得到各自的地址后，再补上前后篇的链接
+        let mut pages = vec![];
+        for (i, part) in parts.iter().enumerate() {
+            let title = part_title(title, i, parts.len());
+            let page = match paths.get(i) {
+                Some(path) => self.edit_article_page(path, &title, part).await?,
+                None => None,
+            };
+            let page = match page {
+                Some(page) => page,
+                None => self.publisher.create(&title, part).await?,
+            };
+            pages.push(page);
+        }
+        if pages.len() > 1 {
+            let urls = pages.iter().map(|p| p.url.clone()).collect::<Vec<_>>();
+            for (i, part) in parts.iter().enumerate() {
+                let nav = article::article_nav(&urls, i);
+                let mut nodes = vec![nav.clone()];
+                nodes.extend(part.iter().cloned());
+                nodes.push(nav);
+                self.publisher.edit(&pages[i].path, &pages[i].title, &nodes).await?;
+            }
+        }
+        // 分篇数量减少时，将多出来的旧文章指向第一篇，避免留下过期的内容
+        for path in paths.iter().skip(pages.len()) {
+            let nodes = [article::p(vec![
+                article::text("内容已更新，请前往 "),
+                article::a(&pages[0].url, "第一篇"),
+                article::text(" 阅读"),
+            ])];
+            if let Err(err) = self.publisher.edit(path, title, &nodes).await {
+                warn!("更新旧文章失败：{} {}", path, err);
+            }
+        }
+        Ok(pages)
+    }
+
+    /// 生成文章开头的画廊信息，展示的内容由配置文件决定
+    pub(super) async fn article_header<T: GalleryInfo>(&self, gallery: &T) -> Result<Vec<Node>> {
+        let mut nodes = vec![];
+        for item in &self.config.telegraph.header {
+            match item {
+                ArticleHeader::Title => {
+                    nodes.push(article::p(vec![article::b(gallery.title_jp())]));
+                    if gallery.title() != gallery.title_jp() {
+                        nodes.push(article::p(vec![article::text(gallery.title())]));
+                    }
+                }
+                ArticleHeader::Tags => {
+                    for (ns, tags) in self.trans.trans_tags(gallery.tags()) {
+                        nodes.push(article::p(vec![
+                            article::b(format!("{}：", ns)),
+                            article::text(tags.join(" ")),
+                        ]));
+                    }
+                }
+                ArticleHeader::Uploader => {
+                    if let Some(uploader) = gallery.uploader() {
+                        // 上传者名称中可能包含空格等字符，需要编码后再放入路径
+                        let mut url = Url::parse("https://exhentai.org/uploader/")?;
+                        url.path_segments_mut()
+                            .map_err(|_| anyhow!("无效的上传者地址"))?
+                            .pop_if_empty()
+                            .push(&uploader);
+                        nodes.push(article::p(vec![
+                            article::b("上传者："),
+                            article::a(url.as_str(), uploader),
+                        ]));
+                    }
+                }
+                ArticleHeader::Posted => {
+                    if let Some(posted) = gallery.posted() {
+                        nodes.push(article::p(vec![
+                            article::b("发布时间："),
+                            article::text(posted.format("%Y-%m-%d %H:%M").to_string()),
+                        ]));
+                    }
+                }
+                ArticleHeader::Grid => {
+                    if let Some(sheet) = ContactSheetEntity::get(gallery.url().id()).await? {
+                        nodes.push(article::img(&sheet.url(&self.storage)));
+                    }
+                }
+                ArticleHeader::Links => {
+                    let mut links = vec![];
+                    // 首次发布时还没有频道消息，会在消息发送后补上，优先使用主频道的消息
+                    let id = gallery.url().id();
+                    let main = self.config.telegram.main.key();
+                    let msg = match MessageEntity::get_by_gallery_in(&main, id).await? {
+                        Some(msg) => Some(msg),
+                        None => MessageEntity::get_by_gallery(id).await?,
+                    };
+                    if let Some(msg) = msg {
+                        let url = url_of(channel_recipient(&msg.channel_id), msg.id);
+                        links.push(article::a(url.as_str(), "频道消息"));
+                        links.push(article::text(" | "));
+                    }
+                    links.push(article::a(&gallery.url().url(), "原始地址"));
+                    nodes.push(article::p(links));
+                }
+            }
+        }
+        Ok(nodes)
+    }
+
+    /// 生成文章末尾的统计信息与相关画廊
+    async fn article_footer<T: GalleryInfo>(&self, gallery: &T) -> Result<Vec<Node>> {
+        let id = gallery.url().id();
+        let mut nodes =
+            vec![article::p(vec![article::text(format!("图片总数：{}", gallery.pages()))])];
+        if let Some(poll) = PollEntity::get_by_gallery(id).await? {
+            nodes.push(article::p(vec![article::text(format!(
+                "当前评分：{:.2}",
+                poll.score * 100.
+            ))]));
+        }
+
+        // 父画廊以及被标记为其他版本的画廊
+        let mut related = vec![];
+        if let Some(parent) = gallery.parent() {
+            related.push(("父画廊", parent));
+        }
+        for relation in GalleryRelationEntity::list(id).await? {
+            if relation.kind == RelationKind::Version {
+                let other = if relation.gallery_id == id {
+                    relation.related_id
+                } else {
+                    relation.gallery_id
+                };
+                related.push(("其他版本", other));
+            }
+        }
+        for (kind, related_id) in related {
+            let entity = match GalleryEntity::get(related_id).await? {
+                Some(v) => v,
+                None => continue,
+            };
+            let url = gallery_preview_url(&self.config.telegram.main, related_id)
+                .await
+                .unwrap_or_else(|_| entity.url().url());
+            nodes.push(article::p(vec![
+                article::b(format!("{}：", kind)),
+                article::a(&url, entity.title_jp()),
+            ]));
+        }
+        Ok(nodes)
+    }
+
+    /// 尝试原地编辑已有的文章，只有确认文章已经不存在时才返回 None，其他错误直接上抛
+    async fn edit_article_page(
+        &self,
+        path: &str,
+        title: &str,
+        nodes: &[Node],
+    ) -> Result<Option<PreviewPage>> {
+        match self.publisher.edit(path, title, nodes).await {
+            Ok(page) if self.publisher.check(&page.url).await? => Ok(Some(page)),
+            Ok(page) => {
+                warn!("文章已失效：{}", page.url);
+                Ok(None)
+            }
+            Err(err) if is_page_not_found(&err) => {
+                warn!("文章已失效：{}", path);
+                Ok(None)
+            }
+            Err(err) => Err(err.context(format!("编辑文章失败：{}", path))),
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{Local, NaiveTime, Utc};
+use tokio::time;
+use tracing::{error, info, warn};
+
+use super::{part_title, ExloliUploader};
+use crate::config::{ArticleHeader, Queue};
+use crate::database::{GalleryEntity, MessageEntity, QueueEntity, TelegraphEntity};
+use crate::ehentai::{EhGallery, GalleryInfo};
+use crate::publisher::PreviewPage;
+
+impl ExloliUploader {
+    /// 立即发布队列中的画廊，无视发布计划
+    pub async fn release(&self, gallery_id: i32) -> Result<()> {
+        let _guard = self.queue_lock.lock().await;
+        self.release_locked(gallery_id).await
+    }
+
+    /// 使用入队时生成的文章发送频道消息，调用前需要持有发布队列的锁
+    async fn release_locked(&self, gallery_id: i32) -> Result<()> {
+        let queued = match QueueEntity::get(gallery_id).await? {
+            Some(q) if q.published_at.is_none() => q,
+            _ => bail!("画廊不在发布队列中"),
+        };
+        info!("发布队列中的画廊：{}", gallery_id);
+        let gallery = match &queued.gallery {
+            Some(json) => serde_json::from_str(json)?,
+            // 旧版本入队的画廊没有保存画廊信息，只能重新获取
+            None => {
+                let entity = GalleryEntity::get(gallery_id).await?.ok_or(anyhow!("找不到画廊"))?;
+                self.ehentai.get_gallery(&entity.url()).await?
+            }
+        };
+        let telegraph = TelegraphEntity::get(gallery_id).await?.ok_or(anyhow!("找不到文章"))?;
+        let (urls, paths) = (telegraph.urls(), telegraph.paths());
+        let (title, n) = (gallery.title_jp(), urls.len());
+        let pages = urls
+            .into_iter()
+            .zip(paths)
+            .enumerate()
+            .map(|(i, (url, path))| PreviewPage { path, url, title: part_title(&title, i, n) })
+            .collect::<Vec<_>>();
+        // 只在需要补上频道消息链接时生成第一篇文章的内容，不会重新发布文章
+        let (article, header_len) =
+            match self.config.telegraph.header.contains(&ArticleHeader::Links) {
+                true => self.build_article(&gallery).await?,
+                false => (vec![vec![]], 0),
+            };
+        self.post(&gallery, false, &pages, &article[0], header_len).await?;
+        QueueEntity::mark_published(gallery_id).await?;
+        Ok(())
+    }
+
+    /// 将队列中的画廊移动到指定位置，位置从 1 开始
+    pub async fn move_in_queue(&self, gallery_id: i32, position: usize) -> Result<()> {
+        let mut queue = QueueEntity::list_pending().await?;
+        let index = queue
+            .iter()
+            .position(|q| q.gallery_id == gallery_id)
+            .ok_or(anyhow!("画廊不在发布队列中"))?;
+        let item = queue.remove(index);
+        queue.insert(position.clamp(1, queue.len() + 1) - 1, item);
+        for (i, item) in queue.iter().enumerate() {
+            QueueEntity::set_position(item.gallery_id, i as i32 + 1).await?;
+        }
+        Ok(())
+    }
+
+    /// 生成文章，然后加入发布队列
+    pub(super) async fn enqueue(&self, gallery: &EhGallery) -> Result<()> {
+        let id = gallery.url.id();
+        let paths = TelegraphEntity::get(id).await?.map(|t| t.paths()).unwrap_or_default();
+        let pages = self.publish_article(gallery, &paths).await?;
+        let parts = pages.iter().map(|p| p.url.clone()).collect::<Vec<_>>();
+        let paths = pages.iter().map(|p| p.path.clone()).collect::<Vec<_>>();
+        TelegraphEntity::create(id, &parts, &paths).await?;
+        GalleryEntity::create(gallery).await?;
+        QueueEntity::push(id, &serde_json::to_string(gallery)?).await?;
+        info!("加入发布队列：{}", gallery.url);
+        Ok(())
+    }
+
+    /// 每分钟检查一次，在发布计划允许时发布队列中的第一个画廊
+    pub(super) async fn run_queue(&self, queue: Queue) {
+        loop {
+            if let Err(err) = self.release_next(&queue).await {
+                error!("发布队列：{}", err);
+            }
+            time::sleep(Duration::from_secs(60)).await;
+        }
+    }
+
+    async fn release_next(&self, queue: &Queue) -> Result<()> {
+        let now = Local::now();
+        if !queue.is_open(now.time()) {
+            return Ok(());
+        }
+        let _guard = self.queue_lock.lock().await;
+        // 间隔以最近一条频道消息为准，直接发布的画廊同样会占用发布间隔
+        let gap = chrono::Duration::from_std(queue.min_gap)?;
+        let last = MessageEntity::last_created().await?;
+        if last.is_some_and(|t| Utc::now().naive_utc() - t < gap) {
+            return Ok(());
+        }
+        if let Some(cap) = queue.daily_cap {
+            let today = now
+                .date_naive()
+                .and_time(NaiveTime::MIN)
+                .and_local_timezone(Local)
+                .earliest()
+                .map(|t| t.naive_utc())
+                .unwrap_or_else(|| Utc::now().naive_utc());
+            if QueueEntity::count_published_since(today).await? >= cap {
+                return Ok(());
+            }
+        }
+        let next = match QueueEntity::list_pending().await?.into_iter().next() {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        if let Err(err) = self.release_locked(next.gallery_id).await {
+            // 避免一个画廊发布失败导致整个队列停滞
+            warn!("发布失败，移到队尾：{} {}", next.gallery_id, err);
+            QueueEntity::move_to_end(next.gallery_id).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use anyhow::Result;
+use chrono::Utc;
+use teloxide::prelude::*;
+use teloxide::types::{InputFile, MessageId};
+use teloxide::utils::html::escape;
+use tracing::{info, warn};
+
+use super::{ExloliUploader, MAX_CAPTION_LEN};
+use crate::bot::review_keyboard;
+use crate::database::{GalleryEntity, ReviewEntity, ReviewStatus, TelegraphEntity};
+use crate::ehentai::{EhGallery, EhGalleryUrl};
+use crate::utils::html_text_len;
+
+impl ExloliUploader {
+    /// 暂缓发布画廊
+    ///
+    /// 图片需要已经上传，会先生成文章，然后在讨论组中发送审核消息，管理员批准后再发布频道消息
+    pub(super) async fn hold(&self, gallery: &EhGallery, rule: &str) -> Result<()> {
+        info!("等待审核：{}", gallery.url);
+        let id = gallery.url.id();
+        let pages = self.publish_article(gallery, &[]).await?;
+        let parts = pages.iter().map(|p| p.url.clone()).collect::<Vec<_>>();
+        let paths = pages.iter().map(|p| p.path.clone()).collect::<Vec<_>>();
+        TelegraphEntity::create(id, &parts, &paths).await?;
+        GalleryEntity::create(gallery).await?;
+
+        let header = format!("规则「{}」要求审核，共 {} 页\n\n", escape(rule), gallery.pages.len());
+        let limit = MAX_CAPTION_LEN - html_text_len(&header);
+        let text = self.create_message_text(gallery, &parts[0], limit, &self.channels[0]).await?;
+        let text = format!("{}{}", header, text);
+        let group = self.config.telegram.main.group_id;
+        let keyboard = review_keyboard(id);
+        let msg = match self.cover_photo(gallery).await {
+            Ok(Some(photo)) => {
+                self.bot
+                    .send_photo(group, InputFile::memory(photo))
+                    .caption(text)
+                    .reply_markup(keyboard)
+                    .await?
+            }
+            _ => {
+                self.bot
+                    .send_message(group, text)
+                    .reply_markup(keyboard)
+                    .disable_web_page_preview(true)
+                    .await?
+            }
+        };
+        ReviewEntity::create(id, rule, msg.id.0).await?;
+        Ok(())
+    }
+
+    /// 发布审核通过的画廊，启用发布队列时加入队列，等待按计划发布
+    pub async fn approve(&self, gallery: &EhGalleryUrl) -> Result<()> {
+        let gallery = self.ehentai.get_gallery(gallery).await?;
+        // 审核期间画廊可能有更新，补上新增的图片
+        self.upload_gallery_image(&gallery).await?;
+        match self.config.queue {
+            Some(_) => self.enqueue(&gallery).await,
+            None => self.publish(&gallery, true).await,
+        }
+    }
+
+    /// 将超时未处理的审核标记为过期，并移除审核消息的按钮
+    pub(super) async fn expire_reviews(&self) -> Result<()> {
+        let expire = match self.config.filter.expire {
+            Some(v) => chrono::Duration::from_std(v)?,
+            None => return Ok(()),
+        };
+        for review in ReviewEntity::list_pending_before(Utc::now().naive_utc() - expire).await? {
+            info!("审核过期：{}", review.gallery_id);
+            ReviewEntity::decide(review.gallery_id, ReviewStatus::Expired, None).await?;
+            let group = self.config.telegram.main.group_id;
+            if let Err(err) =
+                self.bot.edit_message_reply_markup(group, MessageId(review.message_id)).await
+            {
+                warn!("移除审核按钮失败：{}", err);
+            }
+        }
+        Ok(())
+    }
+}
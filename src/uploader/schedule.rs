@@ -0,0 +1,133 @@
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use chrono::{NaiveDateTime, Utc};
+use tokio::time;
+use tracing::{error, warn};
+
+use super::ExloliUploader;
+use crate::database::{GalleryEntity, MessageEntity, UpdateScheduleEntity};
+use crate::ehentai::GalleryInfo;
+
+impl ExloliUploader {
+    /// 为单个画廊指定元数据的检查间隔，为 None 时恢复自动计算，返回下一次检查的时间
+    pub async fn set_update_interval(
+        &self,
+        gallery_id: i32,
+        hours: Option<i32>,
+    ) -> Result<NaiveDateTime> {
+        if MessageEntity::get_by_gallery(gallery_id).await?.is_none() {
+            bail!("画廊没有发布过");
+        }
+        let unchanged = UpdateScheduleEntity::get(gallery_id).await?.map_or(0, |s| s.unchanged);
+        let next = self.next_check_at(gallery_id, unchanged, hours).await?;
+        UpdateScheduleEntity::set_interval(gallery_id, hours, next).await?;
+        Ok(next)
+    }
+
+    /// 每隔 10 分钟从数据库中取出到期的画廊，检查元数据是否有更新，与搜索结果无关
+    pub(super) async fn run_refresher(&self) {
+        loop {
+            if let Err(err) = self.refresh_due().await {
+                error!("更新画廊：{}", err);
+            }
+            time::sleep(Duration::from_secs(600)).await;
+        }
+    }
+
+    async fn refresh_due(&self) -> Result<()> {
+        let batch = self.config.update.batch.unwrap_or(20);
+        for schedule in UpdateScheduleEntity::list_due(Utc::now().naive_utc(), batch).await? {
+            let gallery = match GalleryEntity::get(schedule.gallery_id).await? {
+                Some(v) => v,
+                None => continue,
+            };
+            if let Err(err) = self.try_update(&gallery.url(), false).await {
+                // 同样推迟下一次检查，避免反复请求已经失效的画廊
+                warn!("更新画廊失败：{} {}", gallery.id, err);
+                self.schedule_update(gallery.id, false).await?;
+            }
+            time::sleep(Duration::from_secs(1)).await;
+        }
+        Ok(())
+    }
+
+    /// 记录一次元数据检查，并根据检查结果安排下一次检查
+    ///
+    /// 有变化时重置连续没有变化的次数，管理员指定了间隔时使用指定的间隔
+    pub(super) async fn schedule_update(&self, gallery_id: i32, changed: bool) -> Result<()> {
+        let schedule = UpdateScheduleEntity::get(gallery_id).await?;
+        let unchanged = match (changed, &schedule) {
+            (false, Some(s)) => s.unchanged + 1,
+            _ => 0,
+        };
+        let hours = schedule.and_then(|s| s.interval_hours);
+        let next = self.next_check_at(gallery_id, unchanged, hours).await?;
+        UpdateScheduleEntity::checked(gallery_id, Utc::now().naive_utc(), next, unchanged).await?;
+        Ok(())
+    }
+
+    /// 计算画廊下一次元数据检查的时间，发布时长以第一条频道消息为准
+    async fn next_check_at(
+        &self,
+        gallery_id: i32,
+        unchanged: i32,
+        hours: Option<i32>,
+    ) -> Result<NaiveDateTime> {
+        let now = Utc::now().naive_utc();
+        if let Some(hours) = hours {
+            return Ok(now + chrono::Duration::hours(hours as i64));
+        }
+        let age = match MessageEntity::list_by_gallery(gallery_id).await?.first() {
+            Some(msg) => now.date() - msg.publish_date,
+            None => chrono::Duration::zero(),
+        };
+        let max = self.config.update.max_interval.unwrap_or(Duration::from_secs(60 * 86400));
+        Ok(now + update_interval(age, unchanged, chrono::Duration::from_std(max)?))
+    }
+}
+
+/// 根据画廊的发布时长和连续没有变化的次数，计算两次元数据检查之间的间隔
+///
+/// 2 天内的画廊每天检查，7 天内每 3 天，14 天内每 7 天，其余每 14 天；
+/// 连续没有变化时间隔翻倍，最多翻 8 倍，且不超过 max
+fn update_interval(
+    age: chrono::Duration,
+    unchanged: i32,
+    max: chrono::Duration,
+) -> chrono::Duration {
+    let base = match age {
+        d if d < chrono::Duration::days(2) => 1,
+        d if d < chrono::Duration::days(7) => 3,
+        d if d < chrono::Duration::days(14) => 7,
+        _ => 14,
+    };
+    let days = base << unchanged.clamp(0, 3);
+    chrono::Duration::days(days).min(max)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn update_interval_backoff() {
+        let max = Duration::days(60);
+        let interval = |age, unchanged| update_interval(Duration::days(age), unchanged, max);
+        // 按发布时长决定基础间隔
+        assert_eq!(interval(0, 0), Duration::days(1));
+        assert_eq!(interval(2, 0), Duration::days(3));
+        assert_eq!(interval(7, 0), Duration::days(7));
+        assert_eq!(interval(30, 0), Duration::days(14));
+        // 连续没有变化时翻倍，最多翻 8 倍
+        assert_eq!(interval(0, 1), Duration::days(2));
+        assert_eq!(interval(0, 3), Duration::days(8));
+        assert_eq!(interval(0, 10), Duration::days(8));
+        assert_eq!(interval(2, -1), Duration::days(3));
+        // 不超过最长间隔
+        assert_eq!(interval(30, 3), Duration::days(60));
+        assert_eq!(update_interval(Duration::days(30), 0, Duration::days(5)), Duration::days(5));
+    }
+}
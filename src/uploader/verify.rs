@@ -0,0 +1,306 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use reqwest::header::{CONTENT_LENGTH, ETAG};
+use reqwest::Client;
+use sha1::{Digest, Sha1};
+use teloxide::prelude::*;
+use teloxide::types::MessageId;
+use tokio::time;
+use tracing::{error, info, warn};
+
+use super::images::download_image;
+use super::ExloliUploader;
+use crate::database::{GalleryEntity, ImageEntity, ImageFlag, MessageEntity, TelegraphEntity};
+use crate::ehentai::{EhPageUrl, GalleryInfo};
+use crate::storage::Storage;
+use crate::utils::archive;
+
+/// 图片完整性检查的统计
+#[derive(Debug, Default)]
+pub struct VerifyStats {
+    pub galleries: usize,
+    pub images: usize,
+    pub broken: usize,
+    pub repaired: usize,
+    pub failed: usize,
+}
+
+impl std::fmt::Display for VerifyStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "已检查 {} 个画廊、{} 张图片，发现 {} 张损坏，修复 {} 张，失败 {} 张",
+            self.galleries, self.images, self.broken, self.repaired, self.failed
+        )
+    }
+}
+
+impl ExloliUploader {
+    /// 检查画廊的图片在存储中是否完整，只重新上传损坏的图片，然后重新发布文章
+    ///
+    /// 不指定画廊时分批检查所有已发布的画廊。默认使用 HEAD 请求比较文件大小和 ETag，
+    /// deep 为 true 时会下载图片并校验哈希。检查进度会定期更新到 progress 指定的消息中
+    pub async fn verify_images(
+        &self,
+        gallery: Option<i32>,
+        deep: bool,
+        progress: Option<(ChatId, MessageId)>,
+    ) -> Result<VerifyStats> {
+        let client = Client::builder().timeout(Duration::from_secs(30)).build()?;
+        let mut stats = VerifyStats::default();
+        if let Some(id) = gallery {
+            let gallery = GalleryEntity::get(id).await?.ok_or(anyhow!("找不到画廊"))?;
+            self.verify_gallery(&client, &gallery, deep, &mut stats).await?;
+            return Ok(stats);
+        }
+        let mut after = 0;
+        loop {
+            let galleries = GalleryEntity::list_published_after(after, 50).await?;
+            match galleries.last() {
+                Some(gallery) => after = gallery.id,
+                None => break,
+            }
+            for gallery in &galleries {
+                if let Err(err) = self.verify_gallery(&client, gallery, deep, &mut stats).await {
+                    error!("检查画廊失败：{} {}", gallery.id, err);
+                }
+            }
+            info!("已检查到画廊：{}", after);
+            if let Some((chat, msg)) = progress {
+                let text = format!("检查中……\n{}", stats);
+                if let Err(err) = self.bot.edit_message_text(chat, msg, text).await {
+                    warn!("更新进度失败：{}", err);
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    async fn verify_gallery(
+        &self,
+        client: &Client,
+        gallery: &GalleryEntity,
+        deep: bool,
+        stats: &mut VerifyStats,
+    ) -> Result<()> {
+        stats.galleries += 1;
+        let mut checked = HashSet::new();
+        let mut broken = vec![];
+        for image in ImageEntity::get_by_gallery_id(gallery.id).await? {
+            // 广告图片没有上传，同一张图片也可能出现在多页中
+            if image.flag != ImageFlag::Ok || !checked.insert(image.id) {
+                continue;
+            }
+            stats.images += 1;
+            match self.check_image(client, &image, deep).await {
+                Ok(true) => {}
+                Ok(false) => broken.push(image),
+                // 网络错误不代表文件损坏，下次再检查
+                Err(err) => warn!("检查图片失败：{} {}", image.key, err),
+            }
+        }
+        if broken.is_empty() {
+            return Ok(());
+        }
+        info!("画廊 {} 有 {} 张图片损坏", gallery.id, broken.len());
+        stats.broken += broken.len();
+        let repaired = self.repair_images(gallery, &broken).await?;
+        stats.repaired += repaired;
+        stats.failed += broken.len() - repaired;
+        if repaired > 0 {
+            self.republish(gallery).await?;
+        }
+        Ok(())
+    }
+
+    /// 检查单张图片的文件是否完整
+    ///
+    /// 数据库中没有记录大小或 ETag 时，会以本次检查的结果为准记录下来，供之后比较
+    async fn check_image(&self, client: &Client, image: &ImageEntity, deep: bool) -> Result<bool> {
+        let url = image.url(&self.storage);
+        let (size, etag) = match fetch_integrity(client, &url, &image.key, deep).await? {
+            Some(v) => v,
+            None => return Ok(false),
+        };
+        let size = size.map(|s| s as i64);
+        if size == Some(0)
+            || image.size.is_some_and(|s| size.is_some_and(|size| size != s))
+            || image.etag.is_some() && etag.is_some() && image.etag != etag
+        {
+            return Ok(false);
+        }
+        if let (Some(size), true) = (size, image.size.is_none() || image.etag.is_none()) {
+            ImageEntity::update_integrity(image.id, size, etag.as_deref()).await?;
+        }
+        Ok(true)
+    }
+
+    /// 重新获取损坏的图片并上传，优先从本地的 H@H 下载目录中读取，其次从 E 站下载，返回修复的数量
+    async fn repair_images(
+        &self,
+        gallery: &GalleryEntity,
+        images: &[ImageEntity],
+    ) -> Result<usize> {
+        let local = match &self.config.archive_dir {
+            Some(dir) => match archive::find_gallery(dir, gallery.id)? {
+                Some(path) => archive::index_files(&path)?,
+                None => HashMap::new(),
+            },
+            None => HashMap::new(),
+        };
+        let client = Client::builder().timeout(Duration::from_secs(30)).build()?;
+        // 只有本地找不到图片时才请求 E 站
+        let mut pages = None;
+        let mut repaired = 0;
+        for image in images {
+            let found = local.iter().find(|(sha1, _)| sha1.starts_with(&image.hash));
+            let rst = match found {
+                Some((_, path)) => {
+                    let suffix = path.extension().and_then(|s| s.to_str()).unwrap_or("jpg");
+                    std::fs::read(path).map(|bytes| (bytes, suffix.to_owned())).map_err(Into::into)
+                }
+                None => self.fetch_image(&client, gallery, &mut pages, &image.hash).await,
+            };
+            let rst = match rst {
+                Ok((bytes, suffix)) => self.reupload_image(image, &bytes, &suffix).await,
+                Err(err) => Err(err),
+            };
+            match rst {
+                Ok(()) => repaired += 1,
+                Err(err) => error!("修复图片失败：{} {}", image.id, err),
+            }
+        }
+        Ok(repaired)
+    }
+
+    /// 从 E 站重新下载画廊中指定哈希的图片，返回图片内容和后缀名
+    async fn fetch_image(
+        &self,
+        client: &Client,
+        gallery: &GalleryEntity,
+        pages: &mut Option<Vec<EhPageUrl>>,
+        hash: &str,
+    ) -> Result<(Vec<u8>, String)> {
+        if pages.is_none() {
+            *pages = Some(self.ehentai.get_gallery(&gallery.url()).await?.pages);
+        }
+        let page = pages
+            .iter()
+            .flatten()
+            .find(|p| p.hash() == hash)
+            .ok_or(anyhow!("画廊中找不到该图片"))?;
+        let (_, url) = self.ehentai.get_image_url(page).await?;
+        let fallback = async { Ok(self.ehentai.get_image_url_fallback(page).await?.1) };
+        let (url, bytes, _) = download_image(client, page, url, fallback).await?;
+        Ok((bytes, url.rsplit('.').next().unwrap_or("jpg").to_owned()))
+    }
+
+    async fn reupload_image(&self, image: &ImageEntity, bytes: &[u8], suffix: &str) -> Result<()> {
+        if !matches!(suffix, "jpg" | "png" | "webp") {
+            bail!("不支持的图片格式：{}", suffix);
+        }
+        let filename = format!("{}.{}", image.hash, suffix);
+        self.storage.put(&filename, bytes).await?;
+        ImageEntity::update_key(image.id, self.storage.id(), &filename).await?;
+        ImageEntity::update_integrity(image.id, bytes.len() as i64, None).await?;
+        info!("已重新上传图片：{}", image.id);
+        Ok(())
+    }
+
+    /// 重新检测已上传过的画廊预览是否有效，并重新上传
+    pub async fn recheck(&self, mut galleries: Vec<GalleryEntity>) -> Result<()> {
+        if galleries.is_empty() {
+            galleries = GalleryEntity::list_scans().await?;
+        }
+        for gallery in galleries.iter().rev() {
+            let telegraph =
+                TelegraphEntity::get(gallery.id).await?.ok_or(anyhow!("找不到 telegraph"))?;
+            if MessageEntity::get_by_gallery(gallery.id).await?.is_some() {
+                info!("检测画廊：{}", gallery.url());
+                // 任意一篇失效，都需要重新发布
+                let mut ok = true;
+                for url in telegraph.urls() {
+                    if !self.check_preview(&url).await? {
+                        ok = false;
+                        break;
+                    }
+                }
+                if !ok {
+                    info!("重新上传预览：{}", gallery.url());
+                    if let Err(err) = self.republish(gallery).await {
+                        error!("上传失败：{}", err);
+                    }
+                    time::sleep(Duration::from_secs(60)).await;
+                }
+            }
+            time::sleep(Duration::from_secs(1)).await;
+        }
+        Ok(())
+    }
+}
+
+/// 从我们上传的图片对象键中取出文件名中的页面哈希，其他对象键返回 None
+fn file_hash(key: &str) -> Option<&str> {
+    let stem = key.rsplit('/').next()?.split('.').next()?;
+    (stem.len() == 10 && stem.chars().all(|c| c.is_ascii_hexdigit())).then_some(stem)
+}
+
+/// 请求图片文件，返回文件大小和 ETag，文件无法访问或内容与文件名中的哈希不符时返回 None
+///
+/// deep 为 false 时只发送 HEAD 请求，此时的大小来自 Content-Length
+async fn fetch_integrity(
+    client: &Client,
+    url: &str,
+    key: &str,
+    deep: bool,
+) -> Result<Option<(Option<u64>, Option<String>)>> {
+    let resp = match deep {
+        true => client.get(url).send().await?,
+        false => client.head(url).send().await?,
+    };
+    if !resp.status().is_success() {
+        return Ok(None);
+    }
+    let header = |name| resp.headers().get(name).and_then(|v| v.to_str().ok());
+    let etag = header(ETAG).map(str::to_owned);
+    if !deep {
+        // HEAD 请求没有响应体，Response::content_length 总是返回 0，需要直接读取响应头
+        let size = header(CONTENT_LENGTH).and_then(|v| v.parse().ok());
+        return Ok(Some((size, etag)));
+    }
+    let bytes = resp.bytes().await?;
+    // 相似的图片会复用其他图片的文件，因此以文件名中的哈希为准
+    let sha1 = format!("{:x}", Sha1::digest(&bytes));
+    if file_hash(key).is_some_and(|hash| !sha1.starts_with(hash)) {
+        return Ok(None);
+    }
+    Ok(Some((Some(bytes.len() as u64), etag)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn head_check_reads_content_length() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 1234\r\nETag: \"abc\"\r\n\r\n")
+                .unwrap();
+        });
+
+        let url = format!("http://{}/abcdef0123.jpg", addr);
+        let result = fetch_integrity(&Client::new(), &url, "abcdef0123.jpg", false).await.unwrap();
+        assert_eq!(result, Some((Some(1234), Some("\"abc\"".to_owned()))));
+    }
+}
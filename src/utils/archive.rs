@@ -0,0 +1,27 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use glob::glob;
+use sha1::{Digest, Sha1};
+
+/// 在 H@H 下载目录中查找画廊，下载的画廊目录名以 [画廊 ID] 结尾
+pub fn find_gallery(dir: &Path, gallery_id: i32) -> Result<Option<PathBuf>> {
+    let pattern = format!("{}/*[[]{}]", dir.display(), gallery_id);
+    Ok(glob(&pattern)?.filter_map(|p| p.ok()).find(|p| p.is_dir()))
+}
+
+/// 计算目录中每个文件的 sha1，返回 sha1 到文件路径的映射
+///
+/// 页面哈希是图片 sha1 的前 10 位，因此可以用来匹配画廊中的页面，不需要依赖文件名
+pub fn index_files(dir: &Path) -> Result<HashMap<String, PathBuf>> {
+    let mut result = HashMap::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            let sha1 = format!("{:x}", Sha1::digest(std::fs::read(&path)?));
+            result.insert(sha1, path);
+        }
+    }
+    Ok(result)
+}
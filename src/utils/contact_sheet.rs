@@ -0,0 +1,63 @@
+use anyhow::Result;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, Rgb, RgbImage};
+
+/// 每行的图片数量
+pub const COLUMNS: u32 = 3;
+/// 最多的行数
+pub const ROWS: u32 = 3;
+/// 每一格的大小，大致与漫画页面的比例相同
+const CELL_WIDTH: u32 = 300;
+const CELL_HEIGHT: u32 = 420;
+/// 格子之间的间距
+const GAP: u32 = 6;
+
+/// 从 len 张图片中均匀抽取至多 count 张，返回其下标
+pub fn sample_evenly(len: usize, count: usize) -> Vec<usize> {
+    if len <= count {
+        return (0..len).collect();
+    }
+    (0..count).map(|i| i * (len - 1) / (count - 1)).collect()
+}
+
+/// 将若干张图片按 COLUMNS 列拼成一张网格图片，返回 JPEG 编码后的数据
+pub fn contact_sheet(images: &[DynamicImage]) -> Result<Vec<u8>> {
+    let columns = COLUMNS.min(images.len() as u32);
+    let rows = (images.len() as u32).div_ceil(COLUMNS);
+    let width = columns * CELL_WIDTH + (columns + 1) * GAP;
+    let height = rows * CELL_HEIGHT + (rows + 1) * GAP;
+    let mut sheet = RgbImage::from_pixel(width, height, Rgb([255, 255, 255]));
+
+    for (i, image) in images.iter().enumerate() {
+        let (col, row) = (i as u32 % COLUMNS, i as u32 / COLUMNS);
+        // 保持比例缩放，并在格子中居中
+        let thumb = image.resize(CELL_WIDTH, CELL_HEIGHT, FilterType::Triangle).into_rgb8();
+        let x = GAP + col * (CELL_WIDTH + GAP) + (CELL_WIDTH - thumb.width()) / 2;
+        let y = GAP + row * (CELL_HEIGHT + GAP) + (CELL_HEIGHT - thumb.height()) / 2;
+        imageops::overlay(&mut sheet, &thumb, x as i64, y as i64);
+    }
+
+    let mut data = vec![];
+    JpegEncoder::new_with_quality(&mut data, 85).encode_image(&sheet)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_evenly_keeps_first_and_last() {
+        assert_eq!(sample_evenly(5, 9), vec![0, 1, 2, 3, 4]);
+        assert_eq!(sample_evenly(100, 9), vec![0, 12, 24, 37, 49, 61, 74, 86, 99]);
+    }
+
+    #[test]
+    fn contact_sheet_size() {
+        let images = vec![DynamicImage::new_rgb8(600, 800); 4];
+        let sheet = image::load_from_memory(&contact_sheet(&images).unwrap()).unwrap();
+        assert_eq!(sheet.width(), 3 * CELL_WIDTH + 4 * GAP);
+        assert_eq!(sheet.height(), 2 * CELL_HEIGHT + 3 * GAP);
+    }
+}
@@ -0,0 +1,107 @@
+use anyhow::Result;
+use image::imageops::FilterType;
+use image::DynamicImage;
+
+/// 计算图片的 dHash（差异哈希）
+///
+/// 将图片缩放为 9x8 的灰度图后，逐行比较相邻像素的亮度，得到一个 64 位的指纹。
+/// 重新编码、缩放、轻微调色后的图片，其指纹之间的汉明距离通常很小
+pub fn dhash(data: &[u8]) -> Result<i64> {
+    Ok(dhash_image(&image::load_from_memory(data)?))
+}
+
+/// 同 [`dhash`]，但是接受已经解码的图片
+pub fn dhash_image(image: &DynamicImage) -> i64 {
+    let small = image.resize_exact(9, 8, FilterType::Triangle).into_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] < small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    // NOTE: sqlite 只支持有符号整数，因此这里统一使用 i64 存储
+    hash as i64
+}
+
+/// 逐像素比较两张图片是否为同一张图，只是经过了重新编码或缩放
+///
+/// dHash 相近的图片也可能只是同一张底图配上了不同的文字（例如不同汉化组的翻译），
+/// 因此复用文件前需要用这个更严格的比较确认一次
+pub fn same_picture(a: &[u8], b: &[u8]) -> Result<bool> {
+    Ok(same_picture_image(&image::load_from_memory(a)?, &image::load_from_memory(b)?))
+}
+
+/// 同 [`same_picture`]，但是接受已经解码的图片
+pub fn same_picture_image(a: &DynamicImage, b: &DynamicImage) -> bool {
+    const SIZE: u32 = 256;
+    let ratio = |img: &DynamicImage| img.width() as f32 / img.height() as f32;
+    if (ratio(a) / ratio(b) - 1.).abs() > 0.01 {
+        return false;
+    }
+    let a = a.resize_exact(SIZE, SIZE, FilterType::Triangle).into_luma8();
+    let b = b.resize_exact(SIZE, SIZE, FilterType::Triangle).into_luma8();
+    // 重新编码带来的误差通常很小，而文字的笔画会让一片像素的亮度明显不同
+    let differs = a.pixels().zip(b.pixels()).filter(|(x, y)| x[0].abs_diff(y[0]) > 32).count();
+    differs * 1000 <= (SIZE * SIZE) as usize
+}
+
+/// 计算两个哈希之间的汉明距离
+pub fn hamming(a: i64, b: i64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// 将哈希拆分为 4 段，每段 16 位，与数据库中的 phash_0 ~ phash_3 对应
+pub fn bands(hash: i64) -> [i64; 4] {
+    [(hash >> 48) & 0xffff, (hash >> 32) & 0xffff, (hash >> 16) & 0xffff, hash & 0xffff]
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, Luma};
+
+    use super::*;
+
+    fn pattern(width: u32, height: u32, offset: f32) -> DynamicImage {
+        let image = GrayImage::from_fn(width, height, |x, y| {
+            let (x, y) = (x as f32 / width as f32, y as f32 / height as f32);
+            Luma([(100. + offset + 60. * (x * 7.).sin() * (y * 5.).cos()) as u8])
+        });
+        DynamicImage::ImageLuma8(image)
+    }
+
+    #[test]
+    fn similar_images_have_close_hash() {
+        let a = dhash_image(&pattern(300, 400, 0.));
+        // 缩放并整体调亮后，指纹应当基本不变
+        let b = dhash_image(&pattern(150, 200, 20.));
+        assert!(hamming(a, b) <= 5, "distance: {}", hamming(a, b));
+        // 而不同的图片则相差较远
+        let c = dhash_image(&pattern(400, 300, 0.).rotate90());
+        assert!(hamming(a, c) > 10, "distance: {}", hamming(a, c));
+    }
+
+    #[test]
+    fn same_picture_rejects_different_text() {
+        let a = pattern(300, 400, 0.);
+        assert!(same_picture_image(&a, &pattern(150, 200, 3.)));
+        // 模拟在对话框中写上了不同的文字
+        let mut b = a.to_luma8();
+        for (x, y, p) in b.enumerate_pixels_mut() {
+            if (40..120).contains(&x) && (40..80).contains(&y) && (x + y) % 4 == 0 {
+                *p = Luma([255]);
+            }
+        }
+        assert!(!same_picture_image(&a, &DynamicImage::ImageLuma8(b)));
+        assert!(!same_picture_image(&a, &pattern(300, 300, 0.)));
+    }
+
+    #[test]
+    fn bands_match_sqlite_columns() {
+        let hash = -2i64;
+        assert_eq!(bands(hash), [0xffff, 0xffff, 0xffff, 0xfffe]);
+        assert_eq!(hamming(hash, -1), 1);
+    }
+}
@@ -1,9 +1,15 @@
 use std::borrow::Cow;
 
+use anyhow::Result;
+use image::EncodableLayout;
+
+pub mod archive;
+pub mod contact_sheet;
 pub mod html;
+pub mod imagehash;
 
 /// 左填充空格
-pub fn pad_left(s: &str, len: usize) -> Cow<str> {
+pub fn pad_left(s: &str, len: usize) -> Cow<'_, str> {
     let width = unicode_width::UnicodeWidthStr::width(s);
     if width >= len {
         Cow::Borrowed(s)
@@ -11,3 +17,44 @@ pub fn pad_left(s: &str, len: usize) -> Cow<str> {
         Cow::Owned(" ".repeat(len - width) + s)
     }
 }
+
+/// 计算 telegram HTML 消息在解析后的长度
+///
+/// telegram 以 UTF-16 码元计算长度，并且不计算标签，实体只算作一个字符
+pub fn html_text_len(html: &str) -> usize {
+    let mut len = 0;
+    let mut chars = html.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '<' => chars.by_ref().take_while(|&c| c != '>').for_each(drop),
+            '&' => {
+                chars.by_ref().take_while(|&c| c != ';').for_each(drop);
+                len += 1;
+            }
+            c => len += c.len_utf16(),
+        }
+    }
+    len
+}
+
+/// 检查图片中是否包含二维码
+pub fn has_qrcode(data: &[u8]) -> Result<bool> {
+    let image = image::load_from_memory(data)?.into_luma8();
+    let mut decoder = quircs::Quirc::default();
+    let codes = decoder.identify(image.width() as usize, image.height() as usize, image.as_bytes());
+    Ok(codes.count() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_text_len_counts_parsed_text() {
+        assert_eq!(html_text_len("plain"), 5);
+        assert_eq!(html_text_len(r#"<a href="https://example.com">链接</a>"#), 2);
+        assert_eq!(html_text_len("<b>a</b> &amp; &lt;b&gt;"), 7);
+        // emoji 在 UTF-16 中占两个码元
+        assert_eq!(html_text_len("😀"), 2);
+    }
+}